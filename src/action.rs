@@ -1,213 +1,463 @@
 use util::{Point, Direction};
-use world::{World, ActorRef};
-use actor::Actor;
+use world::{World, CellType, LAVA_DAMAGE};
+use arena::ActorId;
+use actor::PickupKind;
+use scheduler;
+use dungeon;
+use event::{GameEvent, ActorInfo, DamageKind};
 
 static BUMP_DAMAGE : int = 1;
 static FIRE_DAMAGE : int = 3;
 
-pub struct Action {
-	wait_action: Option<WaitAction>,
-	spawn_action: Option<SpawnAction>,
-	move_action: Option<MoveAction>,
-	bump_action: Option<BumpAction>,
-	fire_action: Option<FireAction>
+// energy costs, see scheduler
+static WAIT_COST : int = 100;
+static SPAWN_COST : int = 100;
+static MOVE_COST : int = 100;
+static BUMP_COST : int = 100;
+pub static FIRE_COST : int = 150;
+static STAIRS_COST : int = 100;
+static OPEN_DOOR_COST : int = 100;
+
+pub enum ActionStatus {
+	Done,
+	// the action could not be carried out, with the reason
+	Blocked(String)
 }
 
-struct WaitAction;
+// what happened, published by World::tick once the action is done
+pub struct ActionResult {
+	pub status: ActionStatus,
+	pub events: Vec<GameEvent>
+}
 
-struct SpawnAction {
-	position: Point
+impl ActionResult {
+	pub fn done() -> ActionResult {
+		ActionResult {status: ActionStatus::Done, events: Vec::new()}
+	}
+
+	pub fn blocked(reason: &str) -> ActionResult {
+		ActionResult {status: ActionStatus::Blocked(reason.to_string()), events: Vec::new()}
+	}
+
+	pub fn with_event(mut self, event: GameEvent) -> ActionResult {
+		self.events.push(event);
+		self
+	}
+
+	pub fn is_done(&self) -> bool {
+		match self.status {
+			ActionStatus::Done => true,
+			ActionStatus::Blocked(_) => false
+		}
+	}
 }
 
-struct MoveAction {
-	position: Point
+// Something an actor does with its turn. Brains pick one, World::tick validates
+// and executes it and charges the actor its energy cost.
+pub trait Action {
+	// what the action costs in the current world, e.g. wading is slower
+	fn energy_cost(&self, world: &World) -> int;
+	// whether the action can still be carried out in the current world, the
+	// reason it can't if not
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String>;
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult;
 }
 
-struct BumpAction {
-	position: Point
+pub type BoxedAction = Box<Action + 'static>;
+
+pub fn wait() -> BoxedAction {
+	box WaitAction as BoxedAction
 }
 
-struct FireAction {
-	direction: Direction
+// spawns an actor built from the template with the given id
+pub fn spawn(position: &Point, template: &str) -> BoxedAction {
+	box SpawnAction {position: Point::new(position.x, position.y), template: template.to_string()} as BoxedAction
+}
+
+pub fn move_to(position: &Point) -> BoxedAction {
+	box MoveAction {position: Point::new(position.x, position.y)} as BoxedAction
+}
+
+pub fn bump(position: &Point) -> BoxedAction {
+	box BumpAction {position: Point::new(position.x, position.y)} as BoxedAction
+}
+
+pub fn fire(direction: Direction) -> BoxedAction {
+	box FireAction {direction: direction} as BoxedAction
+}
+
+pub fn open_door(position: &Point) -> BoxedAction {
+	box OpenDoorAction {position: Point::new(position.x, position.y)} as BoxedAction
+}
+
+pub fn descend() -> BoxedAction {
+	box StairsAction {down: true} as BoxedAction
 }
 
-impl Action {
+pub fn ascend() -> BoxedAction {
+	box StairsAction {down: false} as BoxedAction
+}
 
-	pub fn make_wait_action() -> Action {
-		Action {
-			wait_action: Some(WaitAction),
-			spawn_action: None,
-			move_action: None,
-			bump_action: None,
-			fire_action: None
-		}		
+// Validates and executes an action for an actor. A blocked action still costs
+// a wait, so an actor can't stall the scheduler by retrying it forever.
+pub fn perform(action: &BoxedAction, actor: &ActorId, world: &mut World) -> ActionResult {
+	let valid = action.validate(actor, world);
+	let cost = if valid.is_ok() { action.energy_cost(world) } else { scheduler::ACTION_ENERGY };
+	if let Some(acting) = world.actors.get_mut(actor) {
+		acting.spend_energy(cost);
 	}
 
-	pub fn make_spawn_action(position: &Point) -> Action {
-		Action {
-			wait_action: None,
-			spawn_action: Some(SpawnAction {position: Point::new(position.x, position.y)}),
-			move_action: None,
-			bump_action: None,
-			fire_action: None
-		}
+	match valid {
+		Ok(()) => action.execute(actor, world),
+		Err(reason) => ActionResult::blocked(reason.as_slice())
+	}
+}
+
+// hurts target, removing it from the world if that killed it. Terrain damage
+// has no attacker.
+fn damage(attacker: Option<&ActorId>, target: &ActorId, amount: int, kind: DamageKind, world: &mut World, result: ActionResult) -> ActionResult {
+	let attacker_info = attacker.and_then(|attacker| world.actor_info(attacker));
+	let target_died = match world.actors.get_mut(target) {
+		Some(target_actor) => {
+			target_actor.damaged(amount);
+			!target_actor.is_alive()
+		},
+		None => return result
+	};
+
+	let target_info = match world.actor_info(target) {
+		Some(info) => info,
+		None => return result
+	};
+	let mut result = result.with_event(GameEvent::ActorDamaged(attacker_info.clone(), target_info.clone(), amount, kind));
+	if target_died {
+		result = result.with_event(GameEvent::ActorDied(target_info, attacker_info));
+		world.remove_actor(target);
 	}
+	result
+}
 
-	pub fn make_move_action(position: &Point) -> Action {
-		Action {
-			wait_action: None,
-			spawn_action: None,
-			move_action: Some(MoveAction {position: Point::new(position.x, position.y)}),
-			bump_action: None,
-			fire_action: None
-		}
+// Ok if ok holds, otherwise the reason an action can't be carried out
+fn check(ok: bool, reason: &str) -> Result<(), String> {
+	if ok { Ok(()) } else { Err(reason.to_string()) }
+}
+
+struct WaitAction;
+
+impl Action for WaitAction {
+	#[allow(unused_variables)]
+	fn energy_cost(&self, world: &World) -> int {
+		WAIT_COST
 	}
 
-	pub fn make_bump_action(position: &Point) -> Action {
-		Action {
-			wait_action: None,
-			spawn_action: None,
-			move_action: None,
-			bump_action: Some(BumpAction {position: Point::new(position.x, position.y)}),
-			fire_action: None
+	#[allow(unused_variables)]
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String> {
+		Ok(())
+	}
+
+	#[allow(unused_variables)]
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult {
+		ActionResult::done()
+	}
+}
+
+struct SpawnAction {
+	position: Point,
+	template: String
+}
+
+impl Action for SpawnAction {
+	#[allow(unused_variables)]
+	fn energy_cost(&self, world: &World) -> int {
+		SPAWN_COST
+	}
+
+	#[allow(unused_variables)]
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String> {
+		if world.templates.get(self.template.as_slice()).is_none() {
+			return Err(format!("unknown template '{}'", self.template));
 		}
+		check(world.is_walkable(&self.position), "no room to spawn")
 	}
 
-	pub fn make_fire_action(direction: Direction) -> Action {
-		Action {
-			wait_action: None,
-			spawn_action: None,
-			move_action: None,
-			bump_action: None,
-			fire_action: Some(FireAction {direction: direction})
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult {
+		let spawn = match world.templates.build(self.template.as_slice()) {
+			Some(spawn) => spawn,
+			None => return ActionResult::blocked("unknown template")
+		};
+		let spawned = world.add_actor(spawn, self.position.clone());
+		match (world.actor_info(&spawned), world.actor_info(actor)) {
+			(Some(spawned_info), Some(spawner_info)) => ActionResult::done().with_event(GameEvent::ActorSpawned(spawned_info, spawner_info)),
+			_ => ActionResult::done()
 		}
 	}
+}
+
+struct MoveAction {
+	position: Point
+}
 
-	pub fn execute(&self, actor_ref: &ActorRef, world: &mut World) {
+impl Action for MoveAction {
+	fn energy_cost(&self, world: &World) -> int {
+		MOVE_COST * world.cell_type_at(&self.position).move_cost() / 100
+	}
 
-		let mut message: Option<String> = None;
+	#[allow(unused_variables)]
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String> {
+		check(world.is_walkable(&self.position), "the way is blocked")
+	}
 
-		// move
-		if let Some(ref move_action) = self.move_action {
-			let mut picked_up_ammo = false;
-			{
-				let cell = world.get_cell(move_action.position.x, move_action.position.y);
-				if let Some(ref item_actor_ref) = cell.actor {
-					let mut target = item_actor_ref.borrow_mut();
-					target.health = 0;
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult {
+		let mut result = ActionResult::done();
 
-					let pick_up_message = format!("Picked up {}", target.name);
-					message = Some(pick_up_message);
-					picked_up_ammo = true;
-				}	
-			}
+		// anything non-blocking on the cell is an item to pick up
+		let item = world.actor_at(&self.position).and_then(|item| {
+			let pickup = world.actors.get(&item).and_then(|item_actor| item_actor.pickup.as_ref()).map(|pickup| (pickup.kind.clone(), pickup.amount));
+			pickup.map(|pickup| (item, pickup))
+		});
 
-			if picked_up_ammo {
-				if actor_ref.borrow().is_player {
-					world.increase_ammo(5);
+		if let Some((item, (kind, amount))) = item {
+			if let Some(picker) = world.actors.get_mut(actor) {
+				// actors without an inventory just trample the item
+				if let Some(ref mut inventory) = picker.inventory {
+					match kind {
+						PickupKind::Ammo => { inventory.ammo += amount; }
+					}
 				}
-				world.remove_actor(&move_action.position);
 			}
-
-			if world.is_walkable(&move_action.position) {
-				world.set_actor_position(actor_ref, &move_action.position);	
+			if let (Some(picker_info), Some(item_info)) = (world.actor_info(actor), world.actor_info(&item)) {
+				result = result.with_event(GameEvent::ItemPickedUp(picker_info, item_info));
 			}
+			world.remove_actor(&item);
 		}
-		
-		// bump
-		if let Some(ref bump_action) = self.bump_action {
-			let mut target_died = false;
-			{
-				let cell = world.get_cell(bump_action.position.x, bump_action.position.y);	
-				match cell.actor {
-					Some(ref bump_target_actor_ref) => {
-						let mut target = bump_target_actor_ref.borrow_mut();
-						let mut msg_string = format!("{} attacks {}", actor_ref.borrow().name.as_slice(), target.name.as_slice());
-						target.damaged(BUMP_DAMAGE);
-						target_died = !target.is_alive();
-						if target_died {
-							let die_message = format!(" - {} dies",  target.name.as_slice());
-							msg_string.push_str(die_message.as_slice());
-						}
-
-						message = Some(msg_string);
-					},
-					None => { assert!(false) }
-				}
-			}
 
-			if target_died {
-				world.remove_actor(&bump_action.position);
-				if actor_ref.borrow().is_player {
-					world.increase_kills();
-				}
-			}
+		let from = world.actors.get(actor).map(|moving| moving.get_position().clone());
+		world.set_actor_position(actor, &self.position);
+		if let (Some(from), Some(info)) = (from, world.actor_info(actor)) {
+			result = result.with_event(GameEvent::ActorMoved(info, from));
+		}
+
+		let burns = world.actors.get(actor).map_or(false, |moving| moving.can_be_damaged());
+		if burns && world.cell_type_at(&self.position) == CellType::Lava {
+			result = damage(None, actor, LAVA_DAMAGE, DamageKind::Lava, world, result);
 		}
-		
+		result
+	}
+}
 
-		// fire 
-		if let Some(ref fire_action) = self.fire_action {
+struct OpenDoorAction {
+	position: Point
+}
 
-			let mut target_died = false;
-			let mut bullet_position = Point::new(0,0);
-			{
-				let actor = actor_ref.borrow();
-				bullet_position.set(actor.get_position());
-				bullet_position.translate(&fire_action.direction);
-			}
+impl Action for OpenDoorAction {
+	#[allow(unused_variables)]
+	fn energy_cost(&self, world: &World) -> int {
+		OPEN_DOOR_COST
+	}
 
-			loop {
-				let mut done = false;
-
-				if world.is_valid(&bullet_position) {
-					let cell = world.get_cell(bullet_position.x, bullet_position.y);
-					if let Some(ref hit_actor_ref) = cell.actor {
-						let mut target = hit_actor_ref.borrow_mut();
-						let mut msg_string = format!("{} fires at {}", actor_ref.borrow().name.as_slice(), target.name.as_slice()); 
-						
-						target.damaged(FIRE_DAMAGE);
-						target_died = !target.is_alive();
-
-						if target_died {
-							let die_message = format!(" - {} dies",  target.name.as_slice());
-							msg_string.push_str(die_message.as_slice());
-						}
-
-						message = Some(msg_string);
-						done = true;
-					}	
-				} else {
-					done = true;
-				}
-				
-				if done {
-					break;
-				} else {
-					bullet_position.translate(&fire_action.direction);
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String> {
+		if !world.is_closed_door(&self.position) {
+			return Err("no closed door there".to_string());
+		}
+		let next_to_door = world.actors.get(actor).and_then(|opener| opener.position.as_ref()).map_or(false, |p| p.is_adjacent_to(&self.position));
+		check(next_to_door, "too far from the door")
+	}
+
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult {
+		world.set_cell_type(&self.position, CellType::DoorOpen);
+		match world.actor_info(actor) {
+			Some(info) => ActionResult::done().with_event(GameEvent::DoorOpened(info, self.position.clone())),
+			None => ActionResult::done()
+		}
+	}
+}
+
+struct BumpAction {
+	position: Point
+}
+
+impl Action for BumpAction {
+	#[allow(unused_variables)]
+	fn energy_cost(&self, world: &World) -> int {
+		BUMP_COST
+	}
+
+	#[allow(unused_variables)]
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String> {
+		let can_be_hit = match world.actor_at(&self.position) {
+			Some(ref target) => world.actors.get(target).map_or(false, |target_actor| target_actor.can_be_damaged()),
+			None => false
+		};
+		check(can_be_hit, "nothing to attack")
+	}
+
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult {
+		match world.actor_at(&self.position) {
+			Some(target) => damage(Some(actor), &target, BUMP_DAMAGE, DamageKind::Melee, world, ActionResult::done()),
+			None => ActionResult::done()
+		}
+	}
+}
+
+struct FireAction {
+	direction: Direction
+}
+
+impl Action for FireAction {
+	#[allow(unused_variables)]
+	fn energy_cost(&self, world: &World) -> int {
+		FIRE_COST
+	}
+
+	// every shot uses up one round from the shooter's inventory
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String> {
+		let has_ammo = world.actors.get(actor).and_then(|shooter| shooter.inventory.as_ref()).map_or(false, |inventory| inventory.ammo > 0);
+		check(has_ammo, "out of ammo")
+	}
+
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult {
+		let shooter_info = match world.actor_info(actor) {
+			Some(info) => info,
+			None => return ActionResult::done()
+		};
+		if let Some(inventory) = world.actors.get_mut(actor).and_then(|shooter| shooter.inventory.as_mut()) {
+			inventory.ammo -= 1;
+		}
+		let result = ActionResult::done().with_event(GameEvent::ProjectileFired(shooter_info.clone(), self.direction.clone()));
+		let mut bullet_position = match world.actors.get(actor) {
+			Some(shooter) => shooter.get_position().clone(),
+			None => return result
+		};
+		bullet_position.translate(&self.direction);
+
+		while world.is_valid(&bullet_position) {
+			match world.cell_type_at(&bullet_position) {
+				CellType::Rubble => {
+					world.set_cell_type(&bullet_position, CellType::Floor);
+					return result.with_event(GameEvent::RubbleCleared(shooter_info, bullet_position));
+				},
+				cell_type => if cell_type.stops_projectiles() {
+					return result;
 				}
 			}
 
-			if target_died {
-				world.remove_actor(&bullet_position);
-				if actor_ref.borrow().is_player {
-					world.increase_kills();
+			// projectiles fly over anything that can't take damage
+			if let Some(target) = world.actor_at(&bullet_position) {
+				if world.actors.get(&target).map_or(false, |target_actor| target_actor.can_be_damaged()) {
+					return damage(Some(actor), &target, FIRE_DAMAGE, DamageKind::Projectile, world, result);
 				}
 			}
+			bullet_position.translate(&self.direction);
 		}
+		result
+	}
+}
+
+// Takes the player to the next level up or down. The level is swapped at the end
+// of the tick, once everything else about this turn is done.
+struct StairsAction {
+	down: bool
+}
 
-		// spawn
-		if let Some(ref spawn_action) = self.spawn_action {
-			world.add_actor(Actor::kobold(), Point::new(spawn_action.position.x, spawn_action.position.y));
+impl StairsAction {
+	fn target_depth(&self, world: &World) -> Option<uint> {
+		if self.down && world.depth < dungeon::MAX_DEPTH {
+			Some(world.depth + 1)
+		} else if !self.down && world.depth > 1 {
+			Some(world.depth - 1)
+		} else {
+			None
 		}
+	}
+}
+
+impl Action for StairsAction {
+	#[allow(unused_variables)]
+	fn energy_cost(&self, world: &World) -> int {
+		STAIRS_COST
+	}
 
-		// wait
-		if let Some(_) = self.wait_action {
-			// noop
+	fn validate(&self, actor: &ActorId, world: &World) -> Result<(), String> {
+		if *actor != world.player {
+			return Err("only the player takes the stairs".to_string());
 		}
- 
-		// add action message
-		if let Some(message) = message {
-    		world.add_message(message.as_slice());
+		if self.target_depth(world).is_none() {
+			return Err("the stairs lead nowhere".to_string());
 		}
+		let stairs = if self.down { CellType::StairsDown } else { CellType::StairsUp };
+		let position = world.get_player_position();
+		check(world.get_cell(position.x, position.y).cell_type == stairs, "no stairs here")
+	}
+
+	#[allow(unused_variables)]
+	fn execute(&self, actor: &ActorId, world: &mut World) -> ActionResult {
+		world.player_state.travel_to = self.target_depth(world);
+		ActionResult::done()
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use super::{perform, move_to};
+	use util::Point;
+	use world::{World, CellType, LAVA_DAMAGE};
+	use event::{GameEvent, DamageKind};
+
+	fn health_of(world: &World) -> int {
+		world.player().health.as_ref().unwrap().current
+	}
+
+	// the player next to a lava cell
+	fn lava_world() -> World {
+		let mut world = World::new(5, 5);
+		let player = world.player.clone();
+		world.set_actor_position(&player, &Point::new(1, 1));
+		world.set_cell_type(&Point::new(2, 1), CellType::Lava);
+		world
+	}
+
+	#[test]
+	fn stepping_into_lava_burns() {
+		let mut world = lava_world();
+		let player = world.player.clone();
+		let before = health_of(&world);
+
+		let result = perform(&move_to(&Point::new(2, 1)), &player, &mut world);
+
+		assert!(result.is_done());
+		assert!(world.get_player_position() == Point::new(2, 1));
+		assert_eq!(health_of(&world), before - LAVA_DAMAGE);
+		let burned = result.events.iter().any(|event| match *event {
+			GameEvent::ActorDamaged(None, ref target, amount, DamageKind::Lava) => target.is_player && amount == LAVA_DAMAGE,
+			_ => false
+		});
+		assert!(burned);
+	}
+
+	#[test]
+	fn lava_can_kill() {
+		let mut world = lava_world();
+		let player = world.player.clone();
+		world.actors.get_mut(&player).unwrap().health.as_mut().unwrap().current = LAVA_DAMAGE;
+
+		let result = perform(&move_to(&Point::new(2, 1)), &player, &mut world);
+
+		assert!(world.is_game_over());
+		let died = result.events.iter().any(|event| match *event {
+			GameEvent::ActorDied(ref victim, None) => victim.is_player,
+			_ => false
+		});
+		assert!(died);
+	}
+
+	#[test]
+	fn floor_does_not_burn() {
+		let mut world = lava_world();
+		let player = world.player.clone();
+		let before = health_of(&world);
+
+		perform(&move_to(&Point::new(1, 2)), &player, &mut world);
+
+		assert_eq!(health_of(&world), before);
+	}
+}
@@ -1,14 +1,85 @@
-use util::{Point, Color, Direction};
-use world::{World};
+use util::{Point, Color, Direction, GameRng};
+use world::{World, CellType};
 use input;
-use action::Action;
-
-use std::rand;
+use action;
+use action::BoxedAction;
+use pathfinding::PathOptions;
+use perception;
+use morale;
+use behaviour;
+use tactics;
+use behaviour::{Behaviour, Memory, Selector, Status};
+use event::{GameEvent, ActorInfo};
+
+use std::rand::Rng;
 use std::collections::RingBuf;
 
 pub trait Brain {
-	fn think(&self) -> bool;
-	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<Action>;
+	fn think(&self, rng: &mut GameRng) -> bool;
+	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction>;
+	// brain kind followed by its state, as whitespace separated tokens (see load_brain)
+	fn save_state(&self) -> Vec<String>;
+}
+
+pub fn load_brain(tokens: &[&str]) -> Result<Box<Brain + 'static>, String> {
+	if tokens.is_empty() {
+		return Err("missing brain kind".to_string());
+	}
+	match tokens[0] {
+		"player" => Ok(box PlayerBrain::new() as Box<Brain>),
+		"generator" => {
+			if tokens.len() < 2 {
+				return Err("generator brain needs a template to spawn".to_string());
+			}
+			Ok(box GeneratorBrain::new(tokens[1]) as Box<Brain>)
+		},
+		"none" => Ok(box NoBrain::new() as Box<Brain>),
+		"monster" => {
+			let brain = try!(MonsterBrain::load(tokens.slice_from(1)));
+			Ok(box brain as Box<Brain>)
+		},
+		"ranged" => {
+			let monster = try!(MonsterBrain::load(tokens.slice_from(1)));
+			Ok(box RangedBrain {monster: monster} as Box<Brain>)
+		},
+		"tree" => {
+			let brain = try!(TreeBrain::load(tokens.slice_from(1)));
+			Ok(box brain as Box<Brain>)
+		},
+		other => Err(format!("unknown brain kind '{}'", other))
+	}
+}
+
+// A fresh brain of the given kind, as named by actor templates. Generators
+// also need the template id they spawn, trees the behaviour they are built from.
+pub fn new_brain(kind: &str, spawns: Option<&str>, behaviour: Option<&str>) -> Result<Box<Brain + 'static>, String> {
+	match kind {
+		"tree" => match behaviour {
+			Some(description) => {
+				let brain = try!(TreeBrain::new(description));
+				Ok(box brain as Box<Brain>)
+			},
+			None => Err("tree brain needs a 'behaviour'".to_string())
+		},
+		"monster" => Ok(box MonsterBrain::new() as Box<Brain>),
+		"ranged" => Ok(box RangedBrain::new() as Box<Brain>),
+		"none" => Ok(box NoBrain::new() as Box<Brain>),
+		"generator" => match spawns {
+			Some(template) => Ok(box GeneratorBrain::new(template) as Box<Brain>),
+			None => Err("generator brain needs a 'spawns' template".to_string())
+		},
+		other => Err(format!("unknown brain kind '{}'", other))
+	}
+}
+
+fn parse_token(tokens: &[&str], index: uint) -> Result<uint, String> {
+	if index >= tokens.len() {
+		return Err("truncated brain state".to_string());
+	}
+	match tokens[index].parse::<uint>() {
+		Some(value) => Ok(value),
+		None => Err(format!("bad number '{}' in brain state", tokens[index]))
+	}
 }
 
 struct PlayerBrain;
@@ -22,13 +93,18 @@ impl PlayerBrain {
 
 impl Brain for PlayerBrain {
 
-	fn think(&self) -> bool {
+	#[allow(unused_variables)]
+	fn think(&self, rng: &mut GameRng) -> bool {
 		return true;
 	}
 
-	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<Action> {
+	fn save_state(&self) -> Vec<String> {
+		vec!["player".to_string()]
+	}
+
+	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
 		let mut direction;
-		match input::check_for_keypress() {
+		match world.next_key() {
 			Some(key_code) => {
 				match key_code {
 					input::KeyCode::Up => { direction = Direction::North },
@@ -39,8 +115,17 @@ impl Brain for PlayerBrain {
         				world.player_state.toggle_aiming();
 						return None; 
         			},
-        			input::KeyCode::Wait => { return Some(Action::make_wait_action())},
-        			_ => { return None; }
+        			input::KeyCode::Wait => { return Some(action::wait())},
+        			input::KeyCode::Escape => {
+        				world.player_state.quit_requested = true;
+        				return None;
+        			},
+        			input::KeyCode::Descend => {
+        				return take_stairs(current_position, world, CellType::StairsDown, action::descend(), "There are no stairs down here");
+        			},
+        			input::KeyCode::Ascend => {
+        				return take_stairs(current_position, world, CellType::StairsUp, action::ascend(), "There are no stairs up here");
+        			},
 				}
 			},
 			None => {
@@ -51,8 +136,7 @@ impl Brain for PlayerBrain {
         if world.player_state.is_aiming {
         	// fire
         	if world.has_ammo() {
-        		world.decrease_ammo();
-        		return Some(Action::make_fire_action(direction));	
+        		return Some(action::fire(direction));	
         	} else {
         		world.add_message("Out of ammo!");
         		None	
@@ -64,9 +148,11 @@ impl Brain for PlayerBrain {
 	        position.translate(&direction);
 
 	        if world.is_walkable(&position) {
-	        	return Some(Action::make_move_action(&position));	
+	        	return Some(action::move_to(&position));	
 	        } else if world.is_bumpable(&position, false) {
-				return Some(Action::make_bump_action(&position));
+				return Some(action::bump(&position));
+	        } else if world.is_closed_door(&position) {
+	        	return Some(action::open_door(&position));
 	        }
 	        None	
         }
@@ -75,15 +161,36 @@ impl Brain for PlayerBrain {
 	}
 }
 
+fn take_stairs(position: &Point, world: &mut World, stairs: CellType, stairs_action: BoxedAction, missing: &str) -> Option<BoxedAction> {
+	if world.get_cell(position.x, position.y).cell_type == stairs {
+		Some(stairs_action)
+	} else {
+		world.add_message(missing);
+		None
+	}
+}
+
+// how many turns a monster that lost the player looks around before giving up
+static SEARCH_TURNS: uint = 10;
+
 enum MonsterState {
 	Passive,
-	Aggressive
+	// knows where the player is
+	Aggressive,
+	// heading for where it last saw or heard something, then looking around
+	Searching,
+	// running from the player toward a spawner, see morale
+	Fleeing,
+	// waiting at a safe spot until enough others have gathered
+	Regrouping
 }
 
 struct MonsterBrain {
 	state: MonsterState,
 	path: RingBuf<Point>,
-	stuck_on_path_count: uint
+	stuck_on_path_count: uint,
+	last_known_position: Option<Point>,
+	search_turns: uint
 }
 
 impl MonsterBrain {
@@ -91,7 +198,9 @@ impl MonsterBrain {
 		MonsterBrain {
 			state: MonsterState::Passive,
 			path: RingBuf::new(),
-			stuck_on_path_count: 0
+			stuck_on_path_count: 0,
+			last_known_position: None,
+			search_turns: 0
 		}
 	}
 
@@ -99,39 +208,237 @@ impl MonsterBrain {
 		return self.path.len() > 0;
 	}
 
-	fn walk_random(&mut self, current_position: &Point, world: &mut World) -> Option<Action> {
-		let mut next = Point::new(current_position.x, current_position.y);
-		next.translate(&Direction::random_direction());
-		if world.is_walkable(&next) {
-			return Some(Action::make_move_action(&next));
-		} else if world.is_bumpable(&next, true) {
-			return Some(Action::make_bump_action(&next));
-        } else {
-        	return Some(Action::make_wait_action());
-        }
+	pub fn is_aggressive(&self) -> bool {
+		match self.state {
+			MonsterState::Aggressive => true,
+			_ => false
+		}
 	}
-}
 
-impl Brain for MonsterBrain {
-	fn think(&self) -> bool {
-		return true;
+	// <passive|aggressive|searching|fleeing|regrouping> <stuck_on_path_count> <search_turns>
+	// <0|1 last known position> <x> <y> <path length> <x> <y> ...
+	fn load(tokens: &[&str]) -> Result<MonsterBrain, String> {
+		let mut brain = MonsterBrain::new();
+		if tokens.is_empty() {
+			return Err("missing monster state".to_string());
+		}
+		brain.state = match tokens[0] {
+			"passive" => MonsterState::Passive,
+			"aggressive" => MonsterState::Aggressive,
+			"searching" => MonsterState::Searching,
+			"fleeing" => MonsterState::Fleeing,
+			"regrouping" => MonsterState::Regrouping,
+			other => return Err(format!("unknown monster state '{}'", other))
+		};
+		brain.stuck_on_path_count = try!(parse_token(tokens, 1));
+		brain.search_turns = try!(parse_token(tokens, 2));
+		if try!(parse_token(tokens, 3)) == 1 {
+			brain.last_known_position = Some(Point::new(try!(parse_token(tokens, 4)), try!(parse_token(tokens, 5))));
+		}
+		let path_length = try!(parse_token(tokens, 6));
+		for i in range(0, path_length) {
+			let x = try!(parse_token(tokens, 7 + i * 2));
+			let y = try!(parse_token(tokens, 8 + i * 2));
+			brain.path.push_back(Point::new(x, y));
+		}
+		Ok(brain)
 	}
 
-	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<Action> {
-
+	fn is_fleeing(&self) -> bool {
 		match self.state {
-			MonsterState::Passive => {
-				let distance_to_player =  current_position.distance_to(&world.get_player_position());
-				if distance_to_player < 20 {
-					self.state = MonsterState::Aggressive;
+			MonsterState::Fleeing | MonsterState::Regrouping => true,
+			_ => false
+		}
+	}
+
+	// Updates what the monster knows about the player. Seeing beats hearing, and
+	// losing sight of the player starts a search where it was last seen. A monster
+	// on the run only takes note of where the player is.
+	fn perceive(&mut self, current_position: &Point, world: &mut World) {
+		if self.is_fleeing() {
+			if perception::can_see_player(world, current_position) {
+				self.last_known_position = Some(world.get_player_position());
+			}
+			return;
+		}
+
+		if self.is_aggressive() && morale::should_flee(world, current_position) {
+			self.path.clear();
+			self.state = MonsterState::Fleeing;
+			announce(current_position, world, GameEvent::ActorFled);
+			return;
+		}
+
+		let was_aggressive = self.is_aggressive();
+		if perception::can_see_player(world, current_position) {
+			if !was_aggressive {
+				self.path.clear();
+			}
+			self.state = MonsterState::Aggressive;
+			self.last_known_position = Some(world.get_player_position());
+			return;
+		}
+
+		if let Some(noise) = perception::hear(world, current_position) {
+			if self.last_known_position.as_ref() != Some(&noise) {
+				self.path.clear();
+			}
+			self.state = MonsterState::Searching;
+			self.last_known_position = Some(noise);
+			self.search_turns = SEARCH_TURNS;
+			return;
+		}
+
+		if was_aggressive {
+			self.path.clear();
+			self.state = MonsterState::Searching;
+			self.search_turns = SEARCH_TURNS;
+		}
+	}
+
+	fn search(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		let target = self.last_known_position.clone().and_then(|target| if target != *current_position { Some(target) } else { None });
+		let target = match target {
+			Some(target) => target,
+			None => {
+				// arrived, or never knew where to look
+				self.last_known_position = None;
+				if self.search_turns == 0 {
+					self.state = MonsterState::Passive;
 				} else {
-					return self.walk_random(current_position, world);
+					self.search_turns -= 1;
+				}
+				return self.walk_random(current_position, world);
+			}
+		};
+
+		if !self.has_path() {
+			match world.find_path(current_position, &target, &PathOptions::new()) {
+				Some(path) => {
+					for p in path.into_iter() {
+						self.path.push_back(p);
+					}
+				},
+				// can't get there, look around here instead
+				None => self.last_known_position = None
+			}
+		}
+		match self.follow_path(world) {
+			Some(action) => Some(action),
+			None => self.walk_random(current_position, world)
+		}
+	}
+
+	// Heads for the nearest spawner, or just away from the player when there is
+	// none, and waits there for the pack.
+	fn flee(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		let player_position = world.get_player_position();
+		if current_position.is_adjacent_to(&player_position) && !world.is_game_over() {
+			// get away first, and only fight back when cornered
+			return match self.escape_step(current_position, world) {
+				Some(step) => {
+					self.path.clear();
+					Some(action::move_to(&step))
+				},
+				None => Some(action::bump(&player_position))
+			};
+		}
+
+		let spawner = morale::nearest_spawner(world, current_position);
+		let safe = match spawner {
+			Some(ref spawner) => current_position.distance_to(spawner) <= morale::REGROUP_RADIUS,
+			None => current_position.distance_to(&player_position) >= morale::SAFE_DISTANCE
+		};
+		if safe {
+			self.path.clear();
+			self.state = MonsterState::Regrouping;
+			return Some(action::wait());
+		}
+
+		if let Some(spawner) = spawner {
+			if !self.has_path() {
+				if let Some(path) = world.find_path(current_position, &spawner, &PathOptions::new()) {
+					for p in path.into_iter() {
+						self.path.push_back(p);
+					}
 				}
 			}
+			if let Some(action) = self.follow_path(world) {
+				return Some(action);
+			}
+		}
+
+		match self.escape_step(current_position, world) {
+			Some(step) => Some(action::move_to(&step)),
+			None => Some(action::wait())
+		}
+	}
+
+	// a free step further away from the player, if there is one
+	fn escape_step(&self, current_position: &Point, world: &mut World) -> Option<Point> {
+		let steps = world.nav_maps().from_player.downhill(current_position);
+		steps.into_iter().find(|p| world.is_walkable(p))
+	}
+
+	fn regroup(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		if morale::allies_near(world, current_position, morale::ALLY_RADIUS) >= morale::PACK_SIZE {
+			// back to where the player was last seen, with the others in tow
+			self.state = MonsterState::Searching;
+			self.search_turns = SEARCH_TURNS;
+			announce(current_position, world, GameEvent::ActorRallied);
+			return self.search(current_position, world);
+		}
+
+		let player_position = world.get_player_position();
+		if current_position.is_adjacent_to(&player_position) && !world.is_game_over() {
+			return Some(action::bump(&player_position));
+		}
+		Some(action::wait())
+	}
+
+	fn follow_path(&mut self, world: &mut World) -> Option<BoxedAction> {
+		behaviour::follow_path(&mut self.path, world)
+	}
+
+	fn find_step(&mut self, current_position: &Point, world: &mut World) {
+		if behaviour::step_toward_player(current_position, &mut self.path, world) {
+			self.stuck_on_path_count = 0;
+		} else {
+			self.stuck_on_path_count += 1;
+		}
+	}
+
+	fn walk_random(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		Some(behaviour::wander(current_position, world))
+	}
+
+	// what to do about what perceive found out
+	fn decide(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		match self.state {
+			MonsterState::Passive => {
+				return self.walk_random(current_position, world);
+			}
+			MonsterState::Searching => {
+				return self.search(current_position, world);
+			}
+			MonsterState::Fleeing => {
+				return self.flee(current_position, world);
+			}
+			MonsterState::Regrouping => {
+				return self.regroup(current_position, world);
+			}
 			MonsterState::Aggressive => {
 				// attack if possible
 				if current_position.is_adjacent_to(&world.get_player_position()) {
-					return Some(Action::make_bump_action(&world.get_player_position()));
+					return Some(action::bump(&world.get_player_position()));
+				}
+
+				// close by, take a side of the player the others haven't
+				if let Some(id) = world.actor_at(current_position) {
+					if let Some(chosen_action) = tactics::surround(world, &id, current_position) {
+						self.path.clear();
+						return Some(chosen_action);
+					}
 				}
 
 				// re-calculate path more often when close to player
@@ -141,66 +448,251 @@ impl Brain for MonsterBrain {
 				}
 
 				if !self.has_path() {
-					let from = Point::new(current_position.x, current_position.y);
-					let mut to = Point::new(0,0);
-					to.set(&world.get_player_position());
-					
-					if let Some(path) = world.find_path(&from, &to) {
-						for p in path.into_iter() {
-							self.path.push_back(p);	    
-						}
-						self.stuck_on_path_count = 0;
-					} else {
-						self.stuck_on_path_count += 1;
-					}
-				} 
+					self.find_step(current_position, world);
+				}
 				
 				if self.has_path() {
-					if let Some(p) = self.path.pop_front() {
-						if world.is_walkable(&p) {
-						return Some(Action::make_move_action(&p));
-						} else if world.is_bumpable(&p, true) {
-							return Some(Action::make_bump_action(&p));
-				        }	
+					if let Some(action) = self.follow_path(world) {
+						return Some(action);
 					}
-					
 				} else if self.stuck_on_path_count > 2 {
 					self.path.clear();
 					return self.walk_random(current_position, world);
 				}
-				
-				
-				
 			}
 		}
 
-        return Some(Action::make_wait_action());
+        return Some(action::wait());
+	}
+}
+
+impl Brain for MonsterBrain {
+	#[allow(unused_variables)]
+	fn think(&self, rng: &mut GameRng) -> bool {
+		return true;
+	}
+
+	fn save_state(&self) -> Vec<String> {
+		let mut tokens = vec!["monster".to_string()];
+		tokens.push(match self.state {
+			MonsterState::Passive => "passive".to_string(),
+			MonsterState::Aggressive => "aggressive".to_string(),
+			MonsterState::Searching => "searching".to_string(),
+			MonsterState::Fleeing => "fleeing".to_string(),
+			MonsterState::Regrouping => "regrouping".to_string()
+		});
+		tokens.push(self.stuck_on_path_count.to_string());
+		tokens.push(self.search_turns.to_string());
+		match self.last_known_position {
+			Some(ref p) => {
+				tokens.push("1".to_string());
+				tokens.push(p.x.to_string());
+				tokens.push(p.y.to_string());
+			},
+			None => {
+				for _ in range(0, 3u) {
+					tokens.push("0".to_string());
+				}
+			}
+		}
+		tokens.push(self.path.len().to_string());
+		for p in self.path.iter() {
+			tokens.push(p.x.to_string());
+			tokens.push(p.y.to_string());
+		}
+		tokens
+	}
+
+	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		self.perceive(current_position, world);
+		self.decide(current_position, world)
+	}
+}
+
+
+// tells the player about a monster's change of heart, if it's in view
+fn announce(position: &Point, world: &mut World, event: fn(ActorInfo) -> GameEvent) {
+	if !world.is_visible(position) {
+		return;
+	}
+	if let Some(info) = world.actor_at(position).and_then(|id| world.actor_info(&id)) {
+		world.publish(&event(info));
+	}
+}
+
+// ranged monsters back off when the player is nearer than this
+static KEEP_DISTANCE: uint = 3;
+// ammo further than this, in step costs, isn't worth the walk
+static RESTOCK_COST: int = 1500;
+
+// Shoots at the player from a distance while it has ammo. Once it runs out it
+// restocks from ammo lying nearby, or fights like any other monster.
+struct RangedBrain {
+	monster: MonsterBrain
+}
+
+impl RangedBrain {
+	pub fn new() -> RangedBrain {
+		RangedBrain {monster: MonsterBrain::new()}
+	}
+
+	// a step that lines up a shot, backs off or closes in, in that order
+	fn reposition(current_position: &Point, player_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		for direction in [Direction::North, Direction::South, Direction::East, Direction::West].iter() {
+			let mut next = current_position.clone();
+			next.translate(direction);
+			if world.is_walkable(&next) && behaviour::shot_direction(&next, player_position, world).is_some() {
+				return Some(action::move_to(&next));
+			}
+		}
+
+		let steps = if current_position.distance_to(player_position) < KEEP_DISTANCE {
+			world.nav_maps().from_player.downhill(current_position)
+		} else {
+			world.nav_maps().to_player.downhill(current_position)
+		};
+		steps.into_iter().find(|p| world.is_walkable(p)).map(|p| action::move_to(&p))
+	}
+
+	// a step toward the closest ammo, None if there's none near enough
+	fn restock(current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		let steps = {
+			let to_ammo = &world.nav_maps().to_ammo;
+			if to_ammo.value(current_position).map_or(true, |cost| cost > RESTOCK_COST) {
+				return None;
+			}
+			to_ammo.downhill(current_position)
+		};
+		steps.into_iter().find(|p| world.is_walkable(p)).map(|p| action::move_to(&p))
+	}
+}
+
+impl Brain for RangedBrain {
+	#[allow(unused_variables)]
+	fn think(&self, rng: &mut GameRng) -> bool {
+		return true;
+	}
+
+	fn save_state(&self) -> Vec<String> {
+		let mut tokens = self.monster.save_state();
+		tokens[0] = "ranged".to_string();
+		tokens
+	}
+
+	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		self.monster.perceive(current_position, world);
+		if !self.monster.is_aggressive() {
+			return self.monster.decide(current_position, world);
+		}
+		if !behaviour::has_ammo(current_position, world) {
+			if let Some(step) = RangedBrain::restock(current_position, world) {
+				self.monster.path.clear();
+				return Some(step);
+			}
+			return self.monster.decide(current_position, world);
+		}
+
+		let player_position = world.get_player_position();
+		if let Some(direction) = behaviour::shot_direction(current_position, &player_position, world) {
+			return Some(action::fire(direction));
+		}
+		match RangedBrain::reposition(current_position, &player_position, world) {
+			Some(action) => Some(action),
+			None => Some(action::wait())
+		}
+	}
+}
+
+// A monster put together from behaviour nodes, see behaviour::parse.
+struct TreeBrain {
+	description: String,
+	root: Selector,
+	memory: Memory
+}
+
+impl TreeBrain {
+	pub fn new(description: &str) -> Result<TreeBrain, String> {
+		Ok(TreeBrain {
+			description: behaviour::normalize(description),
+			root: try!(behaviour::parse(description)),
+			memory: Memory::new()
+		})
+	}
+
+	// <behaviour> <stuck_count> <path length> <x> <y> ...
+	fn load(tokens: &[&str]) -> Result<TreeBrain, String> {
+		if tokens.is_empty() {
+			return Err("missing behaviour".to_string());
+		}
+		let mut brain = try!(TreeBrain::new(tokens[0]));
+		brain.memory.stuck_count = try!(parse_token(tokens, 1));
+		let path_length = try!(parse_token(tokens, 2));
+		for i in range(0, path_length) {
+			let x = try!(parse_token(tokens, 3 + i * 2));
+			let y = try!(parse_token(tokens, 4 + i * 2));
+			brain.memory.path.push_back(Point::new(x, y));
+		}
+		Ok(brain)
+	}
+}
+
+impl Brain for TreeBrain {
+	#[allow(unused_variables)]
+	fn think(&self, rng: &mut GameRng) -> bool {
+		return true;
+	}
+
+	fn save_state(&self) -> Vec<String> {
+		let mut tokens = vec!["tree".to_string(), self.description.clone()];
+		tokens.push(self.memory.stuck_count.to_string());
+		tokens.push(self.memory.path.len().to_string());
+		for p in self.memory.path.iter() {
+			tokens.push(p.x.to_string());
+			tokens.push(p.y.to_string());
+		}
+		tokens
+	}
+
+	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		match self.root.run(current_position, &mut self.memory, world) {
+			Status::Acted(chosen_action) => Some(chosen_action),
+			// nothing acted, sit the turn out
+			_ => Some(action::wait())
+		}
 	}
 }
 
-struct GeneratorBrain;
+struct GeneratorBrain {
+	// template id of what comes out
+	spawns: String
+}
+
 impl GeneratorBrain {
-	pub fn new() -> GeneratorBrain {
-		GeneratorBrain
+	pub fn new(spawns: &str) -> GeneratorBrain {
+		GeneratorBrain {spawns: spawns.to_string()}
 	}
 }
 
 impl Brain for GeneratorBrain {
-	fn think(&self) -> bool {
-		let should_spawn = rand::random::<uint>()%10 == 1;
+	fn think(&self, rng: &mut GameRng) -> bool {
+		let should_spawn = rng.gen_range(0u, 10) == 1;
 		return should_spawn;
 	}
 
-	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<Action> {
-		let direction = Direction::random_direction();
+	fn save_state(&self) -> Vec<String> {
+		vec!["generator".to_string(), self.spawns.clone()]
+	}
+
+	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
+		let direction = Direction::random_direction(&mut world.rng);
 
 		let mut spawn_position = Point::new(current_position.x, current_position.y);
 		spawn_position.translate(&direction);
 
         if world.is_walkable(&spawn_position) {
-        	Some(Action::make_spawn_action(&spawn_position))
+        	Some(action::spawn(&spawn_position, self.spawns.as_slice()))
         } else {
-        	Some(Action::make_wait_action())
+        	Some(action::wait())
         }
 	}
 }
@@ -213,98 +705,137 @@ impl NoBrain {
 }
 
 impl Brain for NoBrain {
-	fn think(&self) -> bool {
+	#[allow(unused_variables)]
+	fn think(&self, rng: &mut GameRng) -> bool {
 		return false;
 	}
 
+	fn save_state(&self) -> Vec<String> {
+		vec!["none".to_string()]
+	}
+
 	#[allow(unused_variables)]
-	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<Action> {
+	fn act(&mut self, current_position: &Point, world: &mut World) -> Option<BoxedAction> {
 		None
 	}
 }
 
+// Components. An actor only carries the ones that apply to it, systems look
+// at the actors that have what they need and skip the rest.
+
+#[deriving(Clone)]
+pub struct Renderable {
+	pub glyph: char,
+	pub color: Color
+}
+
+pub struct Health {
+	pub current: int,
+	pub max: int
+}
+
+// thinking and acting, scheduled by speed and energy (see scheduler)
+pub struct Ai {
+	pub brain: Box<Brain + 'static>,
+	pub speed: int,
+	pub energy: int
+}
+
+#[deriving(Clone, PartialEq)]
+pub enum PickupKind {
+	Ammo
+}
+
+// picked up by walking onto it
+pub struct Pickup {
+	pub kind: PickupKind,
+	pub amount: uint
+}
+
+pub struct Inventory {
+	pub ammo: uint
+}
+
+// other actors can't share a cell with a blocker
+pub struct Blocker;
+
 pub struct Actor {
-	pub position: Point,
-    pub glyph : char,
-    pub color : Color,
-    pub name : String,
-    pub is_player : bool,
-    pub is_solid : bool,
-    pub health: int,
-    pub brain : Box<Brain + 'static>
+	pub name: String,
+	pub is_player: bool,
+	// id of the template it was built from, if any
+	pub template: Option<String>,
+	pub position: Option<Point>,
+	pub renderable: Option<Renderable>,
+	pub health: Option<Health>,
+	pub ai: Option<Ai>,
+	pub pickup: Option<Pickup>,
+	pub inventory: Option<Inventory>,
+	pub blocker: Option<Blocker>
 }
 
 
 impl Actor {
-	pub fn player() -> Actor {
+	// an actor with just a name, add components to it
+	pub fn new(name: &str) -> Actor {
 		Actor {
-			position: Point::new(0,0), 
-			glyph: '@', 
-			color: Color::red(), 
-			name: "Player".to_string(),
-			is_player: true, 
-			is_solid : true, 
-			health: 10, 
-			brain: box PlayerBrain::new()
+			name: name.to_string(),
+			is_player: false,
+			template: None,
+			position: None,
+			renderable: None,
+			health: None,
+			ai: None,
+			pickup: None,
+			inventory: None,
+			blocker: None
 		}
 	}
 
-	pub fn kobold() -> Actor {
-		Actor {
-			position: Point::new(0,0), 
-			glyph: 'k', 
-			color: Color::green(), 
-			name: "Kobold".to_string(), 
-			is_player: false, 
-			is_solid: true, 
-			health: 2, 
-			brain: box MonsterBrain::new()
-		}
-	}
-
-	pub fn kobold_generator() -> Actor {
-		Actor { 
-			position: Point::new(0,0), 
-			glyph: 'G', 
-			color: Color::purple(), 
-			name: "Kobold generator".to_string(),  
-			is_player: false, 
-			is_solid: true, 
-			health: 5, 
-			brain: box GeneratorBrain::new()
-		}	
-	}
-
-	pub fn ammo_crate() -> Actor {
-		Actor {
-			position: Point::new(0,0), 
-			glyph: '*', 
-			color: Color::light_blue(), 
-			name: "Ammo crate".to_string(), 
-			is_player: false, 
-			is_solid: false, 
-			health: 1, 
-			brain: box NoBrain::new()}	
+	pub fn player() -> Actor {
+		let mut actor = Actor::new("Player");
+		actor.is_player = true;
+		actor.renderable = Some(Renderable {glyph: '@', color: Color::red()});
+		actor.health = Some(Health {current: 10, max: 10});
+		actor.ai = Some(Ai {brain: box PlayerBrain::new(), speed: 100, energy: 0});
+		actor.inventory = Some(Inventory {ammo: 0});
+		actor.blocker = Some(Blocker);
+		actor
 	}
 
+	// only for actors placed in the world
 	pub fn get_position(&self) -> &Point {
-		return &self.position;
+		self.position.as_ref().expect("actor has no position")
 	}
 
 	pub fn set_position(&mut self, position: Point) {
-		self.position.x = position.x;
-		self.position.y = position.y;
+		self.position = Some(position);
+	}
+
+	pub fn is_solid(&self) -> bool {
+		self.blocker.is_some()
 	}
 
 	pub fn damaged(&mut self, damage: int) {
-		self.health -= damage;
+		if let Some(ref mut health) = self.health {
+			health.current -= damage;
+		}
+	}
+
+	pub fn can_be_damaged(&self) -> bool {
+		self.health.is_some()
 	}
 
+	// actors without health can't die, only be removed
 	pub fn is_alive(&self) -> bool {
-		return self.health > 0;
+		match self.health {
+			Some(ref health) => health.current > 0,
+			None => true
+		}
 	}
 
-	pub fn act(&mut self, world: &mut World) -> Option<Action> {
-		return self.brain.act(&self.position, world);
+	pub fn spend_energy(&mut self, cost: int) {
+		if let Some(ref mut ai) = self.ai {
+			ai.energy -= cost;
+		}
 	}
 }
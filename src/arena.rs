@@ -0,0 +1,104 @@
+use actor::Actor;
+
+// Handle to an actor in an ActorArena. The generation changes whenever a slot is
+// reused, so an id kept around after its actor was removed just stops resolving
+// instead of pointing at whoever took the slot.
+#[deriving(Clone, PartialEq, Eq, Hash)]
+pub struct ActorId {
+	index: uint,
+	generation: uint
+}
+
+struct Slot {
+	generation: uint,
+	actor: Option<Actor>
+}
+
+pub struct ActorArena {
+	slots: Vec<Slot>,
+	free: Vec<uint>,
+	count: uint
+}
+
+impl ActorArena {
+	pub fn new() -> ActorArena {
+		ActorArena {slots: Vec::new(), free: Vec::new(), count: 0}
+	}
+
+	pub fn insert(&mut self, actor: Actor) -> ActorId {
+		self.count += 1;
+		match self.free.pop() {
+			Some(index) => {
+				let slot = &mut self.slots[index];
+				slot.generation += 1;
+				slot.actor = Some(actor);
+				ActorId {index: index, generation: slot.generation}
+			},
+			None => {
+				self.slots.push(Slot {generation: 0, actor: Some(actor)});
+				ActorId {index: self.slots.len() - 1, generation: 0}
+			}
+		}
+	}
+
+	pub fn remove(&mut self, id: &ActorId) -> Option<Actor> {
+		if !self.contains(id) {
+			return None;
+		}
+		self.count -= 1;
+		self.free.push(id.index);
+		self.slots[id.index].actor.take()
+	}
+
+	pub fn contains(&self, id: &ActorId) -> bool {
+		self.get(id).is_some()
+	}
+
+	pub fn get(&self, id: &ActorId) -> Option<&Actor> {
+		if id.index >= self.slots.len() {
+			return None;
+		}
+		let slot = &self.slots[id.index];
+		if slot.generation != id.generation {
+			return None;
+		}
+		slot.actor.as_ref()
+	}
+
+	pub fn get_mut(&mut self, id: &ActorId) -> Option<&mut Actor> {
+		if id.index >= self.slots.len() {
+			return None;
+		}
+		let slot = &mut self.slots[id.index];
+		if slot.generation != id.generation {
+			return None;
+		}
+		slot.actor.as_mut()
+	}
+
+	// ids of all live actors in slot order. A snapshot, so the arena can be
+	// changed while walking it.
+	pub fn ids(&self) -> Vec<ActorId> {
+		let mut ids = Vec::with_capacity(self.count);
+		for (index, slot) in self.slots.iter().enumerate() {
+			if slot.actor.is_some() {
+				ids.push(ActorId {index: index, generation: slot.generation});
+			}
+		}
+		ids
+	}
+
+	pub fn len(&self) -> uint {
+		self.count
+	}
+
+	// slots are kept so stale ids stay stale, and handed out again lowest first
+	pub fn clear(&mut self) {
+		self.free.clear();
+		for index in range(0, self.slots.len()).rev() {
+			self.slots[index].actor = None;
+			self.free.push(index);
+		}
+		self.count = 0;
+	}
+}
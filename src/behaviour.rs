@@ -0,0 +1,516 @@
+use util::{Point, Direction};
+use world::World;
+use action;
+use action::BoxedAction;
+use pathfinding::PathOptions;
+use morale;
+use perception;
+use tactics;
+
+use std::collections::RingBuf;
+
+// how close to the player a monster looks for a way around others, and how
+// many cells that search may look at
+static DETOUR_DISTANCE: uint = 8;
+static DETOUR_BUDGET: uint = 200;
+// shots are only taken from this close
+static SHOT_RANGE: uint = 8;
+// failed steps in a row before a monster gives up and wanders
+static STUCK_LIMIT: uint = 2;
+
+// What a monster built from behaviours remembers between turns.
+pub struct Memory {
+	pub path: RingBuf<Point>,
+	pub stuck_count: uint
+}
+
+impl Memory {
+	pub fn new() -> Memory {
+		Memory {path: RingBuf::new(), stuck_count: 0}
+	}
+}
+
+// How running a node went. Actions end the turn, conditions only succeed or
+// fail.
+pub enum Status {
+	Acted(BoxedAction),
+	Succeeded,
+	Failed
+}
+
+// A piece of monster behaviour. It either picks an action, or succeeds or fails
+// without one, leaving the turn to whatever comes after it.
+pub trait Behaviour {
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status;
+}
+
+// Tries its children in order until one of them acts or succeeds.
+pub struct Selector {
+	children: Vec<Box<Behaviour + 'static>>
+}
+
+impl Behaviour for Selector {
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		for child in self.children.iter() {
+			match child.run(position, memory, world) {
+				Status::Failed => continue,
+				status => return status
+			}
+		}
+		Status::Failed
+	}
+}
+
+// Runs its children in order while they succeed, e.g. a condition and then
+// what to do about it. An action ends the run, a failure fails it.
+struct Sequence {
+	children: Vec<Box<Behaviour + 'static>>
+}
+
+impl Behaviour for Sequence {
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		for child in self.children.iter() {
+			match child.run(position, memory, world) {
+				Status::Succeeded => continue,
+				status => return status
+			}
+		}
+		Status::Succeeded
+	}
+}
+
+// The behaviour of a data file definition, a comma separated list of the node
+// names below tried as a selector. sequence(...) and selector(...) group nodes,
+// e.g. "attack_adjacent, sequence(sees_player, approach), wander".
+pub fn parse(description: &str) -> Result<Selector, String> {
+	Ok(Selector {children: try!(parse_list(description))})
+}
+
+// names and descriptions differ only in whitespace, this is the one saves use
+pub fn normalize(description: &str) -> String {
+	description.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn parse_list(source: &str) -> Result<Vec<Box<Behaviour + 'static>>, String> {
+	let mut children = Vec::new();
+	for part in try!(split_list(source)).iter() {
+		children.push(try!(node(part.trim())));
+	}
+	Ok(children)
+}
+
+// source split at the commas that aren't inside parentheses
+fn split_list(source: &str) -> Result<Vec<&str>, String> {
+	let mut parts = Vec::new();
+	let mut depth = 0u;
+	let mut start = 0u;
+	for (i, c) in source.char_indices() {
+		match c {
+			'(' => depth += 1,
+			')' => {
+				if depth == 0 {
+					return Err(format!("unexpected ')' in '{}'", source));
+				}
+				depth -= 1;
+			},
+			',' if depth == 0 => {
+				parts.push(source.slice(start, i));
+				start = i + 1;
+			},
+			_ => {}
+		}
+	}
+	if depth > 0 {
+		return Err(format!("missing ')' in '{}'", source));
+	}
+	parts.push(source.slice_from(start));
+	Ok(parts)
+}
+
+fn node(text: &str) -> Result<Box<Behaviour + 'static>, String> {
+	let open = match text.find('(') {
+		Some(open) => open,
+		None => return leaf(text)
+	};
+	if !text.ends_with(")") {
+		return Err(format!("unexpected text after ')' in '{}'", text));
+	}
+	let children = try!(parse_list(text.slice(open + 1, text.len() - 1)));
+	match text.slice_to(open).trim() {
+		"sequence" => Ok(box Sequence {children: children} as Box<Behaviour>),
+		"selector" => Ok(box Selector {children: children} as Box<Behaviour>),
+		other => Err(format!("unknown group '{}'", other))
+	}
+}
+
+fn leaf(name: &str) -> Result<Box<Behaviour + 'static>, String> {
+	match name {
+		"attack_adjacent" => Ok(box AttackAdjacent as Box<Behaviour>),
+		"follow_path" => Ok(box FollowPath as Box<Behaviour>),
+		"approach" => Ok(box Approach as Box<Behaviour>),
+		"surround" => Ok(box Surround as Box<Behaviour>),
+		"shoot" => Ok(box Shoot as Box<Behaviour>),
+		"flee_when_wounded" => Ok(box FleeWhenWounded as Box<Behaviour>),
+		"wander_when_stuck" => Ok(box WanderWhenStuck as Box<Behaviour>),
+		"wander" => Ok(box Wander as Box<Behaviour>),
+		"wait" => Ok(box Wait as Box<Behaviour>),
+		"sees_player" => Ok(box SeesPlayer as Box<Behaviour>),
+		"heard_noise" => Ok(box HeardNoise as Box<Behaviour>),
+		"" => Err("empty behaviour node".to_string()),
+		other => Err(format!("unknown behaviour '{}'", other))
+	}
+}
+
+// adjacent to the player -> attack
+struct AttackAdjacent;
+
+impl Behaviour for AttackAdjacent {
+	#[allow(unused_variables)]
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		let player_position = world.get_player_position();
+		if !world.is_game_over() && position.is_adjacent_to(&player_position) {
+			Status::Acted(action::bump(&player_position))
+		} else {
+			Status::Failed
+		}
+	}
+}
+
+// has a path -> take its next step
+struct FollowPath;
+
+impl Behaviour for FollowPath {
+	#[allow(unused_variables)]
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		acted(follow_path(&mut memory.path, world))
+	}
+}
+
+// a way toward the player -> step along it. The way is known from anywhere,
+// gate it behind sees_player or heard_noise for a monster that has to notice
+// the player first.
+struct Approach;
+
+impl Behaviour for Approach {
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		if world.is_game_over() || !step_toward_player(position, &mut memory.path, world) {
+			memory.stuck_count += 1;
+			return Status::Failed;
+		}
+		memory.stuck_count = 0;
+		acted(follow_path(&mut memory.path, world))
+	}
+}
+
+// near the player -> go for a side the pack hasn't taken, see tactics
+struct Surround;
+
+impl Behaviour for Surround {
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		let id = match world.actor_at(position) {
+			Some(id) => id,
+			None => return Status::Failed
+		};
+		let chosen_action = tactics::surround(world, &id, position);
+		if chosen_action.is_some() {
+			memory.path.clear();
+		}
+		acted(chosen_action)
+	}
+}
+
+// ammo and a clear line to the player -> fire
+struct Shoot;
+
+impl Behaviour for Shoot {
+	#[allow(unused_variables)]
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		if world.is_game_over() || !has_ammo(position, world) {
+			return Status::Failed;
+		}
+		acted(shot_direction(position, &world.get_player_position(), world).map(|direction| action::fire(direction)))
+	}
+}
+
+// hurt and alone -> back away from the player
+struct FleeWhenWounded;
+
+impl Behaviour for FleeWhenWounded {
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		if !morale::should_flee(world, position) {
+			return Status::Failed;
+		}
+		memory.path.clear();
+		let steps = world.nav_maps().from_player.downhill(position);
+		acted(steps.into_iter().find(|p| world.is_walkable(p)).map(|p| action::move_to(&p)))
+	}
+}
+
+// stuck for a while -> forget the path and wander
+struct WanderWhenStuck;
+
+impl Behaviour for WanderWhenStuck {
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		if memory.stuck_count <= STUCK_LIMIT {
+			return Status::Failed;
+		}
+		memory.path.clear();
+		Status::Acted(wander(position, world))
+	}
+}
+
+struct Wander;
+
+impl Behaviour for Wander {
+	#[allow(unused_variables)]
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		Status::Acted(wander(position, world))
+	}
+}
+
+struct Wait;
+
+impl Behaviour for Wait {
+	#[allow(unused_variables)]
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		Status::Acted(action::wait())
+	}
+}
+
+// Conditions, they succeed or fail without acting.
+
+// the player is in sight, see perception
+struct SeesPlayer;
+
+impl Behaviour for SeesPlayer {
+	#[allow(unused_variables)]
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		check(perception::can_see_player(world, position))
+	}
+}
+
+// a noise the player made is within earshot
+struct HeardNoise;
+
+impl Behaviour for HeardNoise {
+	#[allow(unused_variables)]
+	fn run(&self, position: &Point, memory: &mut Memory, world: &mut World) -> Status {
+		check(perception::hear(world, position).is_some())
+	}
+}
+
+fn acted(chosen_action: Option<BoxedAction>) -> Status {
+	match chosen_action {
+		Some(chosen_action) => Status::Acted(chosen_action),
+		None => Status::Failed
+	}
+}
+
+fn check(condition: bool) -> Status {
+	if condition { Status::Succeeded } else { Status::Failed }
+}
+
+// The parts the nodes are made of, shared with the hand written brains.
+
+// the next step of path, opening doors on the way
+pub fn follow_path(path: &mut RingBuf<Point>, world: &mut World) -> Option<BoxedAction> {
+	if let Some(p) = path.pop_front() {
+		if world.is_walkable(&p) {
+			return Some(action::move_to(&p));
+		} else if world.is_bumpable(&p, true) {
+			return Some(action::bump(&p));
+		} else if world.is_closed_door(&p) {
+			// walk through once it's open
+			path.push_front(p.clone());
+			return Some(action::open_door(&p));
+		}
+		// something is in the way, find another path next turn
+		path.clear();
+	}
+	None
+}
+
+// Queues the next step toward the player from the shared distance map. When
+// other monsters crowd the way down, a short path around them is queued
+// instead. False if there is no way.
+pub fn step_toward_player(position: &Point, path: &mut RingBuf<Point>, world: &mut World) -> bool {
+	let player_position = world.get_player_position();
+	let steps = world.nav_maps().to_player.downhill(position);
+	if steps.is_empty() {
+		return false;
+	}
+
+	let free = steps.iter().find(|p| world.is_walkable(*p) || world.is_closed_door(*p)).map(|p| p.clone());
+	if let Some(step) = free {
+		path.push_back(step);
+		return true;
+	}
+
+	if position.distance_to(&player_position) < DETOUR_DISTANCE {
+		let mut options = PathOptions::new();
+		options.avoid_actors = true;
+		options.max_nodes = DETOUR_BUDGET;
+		if let Some(detour) = world.find_path(position, &player_position, &options) {
+			for p in detour.into_iter() {
+				path.push_back(p);
+			}
+			return true;
+		}
+	}
+	false
+}
+
+// a step in a random direction, attacking the player if it's there
+pub fn wander(position: &Point, world: &mut World) -> BoxedAction {
+	let mut next = Point::new(position.x, position.y);
+	next.translate(&Direction::random_direction(&mut world.rng));
+	if world.is_walkable(&next) {
+		action::move_to(&next)
+	} else if world.is_bumpable(&next, true) {
+		action::bump(&next)
+	} else {
+		action::wait()
+	}
+}
+
+pub fn has_ammo(position: &Point, world: &World) -> bool {
+	let id = match world.actor_at(position) {
+		Some(id) => id,
+		None => return false
+	};
+	match world.actors.get(&id) {
+		Some(actor) => actor.inventory.as_ref().map_or(false, |inventory| inventory.ammo > 0),
+		None => false
+	}
+}
+
+// The direction to fire from from to hit target: in a straight line, in range,
+// with nothing that stops a shot or could be hit first in between.
+pub fn shot_direction(from: &Point, target: &Point, world: &World) -> Option<Direction> {
+	let direction = if from.x == target.x && from.y > target.y {
+		Direction::North
+	} else if from.x == target.x && from.y < target.y {
+		Direction::South
+	} else if from.y == target.y && from.x < target.x {
+		Direction::East
+	} else if from.y == target.y && from.x > target.x {
+		Direction::West
+	} else {
+		return None;
+	};
+	if from.distance_to(target) > SHOT_RANGE {
+		return None;
+	}
+
+	let mut p = from.clone();
+	p.translate(&direction);
+	while p != *target {
+		if world.cell_type_at(&p).stops_projectiles() {
+			return None;
+		}
+		if let Some(id) = world.actor_at(&p) {
+			if world.actors.get(&id).map_or(false, |actor| actor.can_be_damaged()) {
+				return None;
+			}
+		}
+		p.translate(&direction);
+	}
+	Some(direction)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{parse, normalize, Behaviour, Memory, Status};
+	use util::Point;
+	use world::{World, CellType};
+	use actor::{Actor, Blocker, new_brain};
+	use action::perform;
+
+	// the player at 1,1 and a monster at 7,1, with a wall between them unless
+	// in_sight
+	fn tree_world(in_sight: bool) -> World {
+		let rows = [
+			"....#.....",
+			"....#.....",
+			"....#.....",
+			".........."];
+		let mut world = World::new(10, 4);
+		for (y, row) in rows.iter().enumerate() {
+			for (x, glyph) in row.chars().enumerate() {
+				world.set_cell_type(&Point::new(x, y), CellType::from_glyph(glyph).unwrap());
+			}
+		}
+		if in_sight {
+			world.set_cell_type(&Point::new(4, 1), CellType::Floor);
+		}
+		let player = world.player.clone();
+		world.set_actor_position(&player, &Point::new(1, 1));
+		let mut monster = Actor::new("berserker");
+		monster.blocker = Some(Blocker);
+		world.add_actor(monster, Point::new(7, 1));
+		world.update_fov();
+		world
+	}
+
+	fn run(description: &str, world: &mut World) -> &'static str {
+		let tree = parse(description).unwrap();
+		match tree.run(&Point::new(7, 1), &mut Memory::new(), world) {
+			Status::Acted(_) => "acted",
+			Status::Succeeded => "succeeded",
+			Status::Failed => "failed"
+		}
+	}
+
+	#[test]
+	fn conditions_gate_a_sequence() {
+		assert_eq!(run("sequence(sees_player, approach)", &mut tree_world(false)), "failed");
+		assert_eq!(run("sequence(sees_player, approach)", &mut tree_world(true)), "acted");
+	}
+
+	#[test]
+	fn sequence_stops_on_failure() {
+		// nobody made a sound, so the wander never runs
+		assert_eq!(run("sequence(heard_noise, wander)", &mut tree_world(true)), "failed");
+	}
+
+	#[test]
+	fn selector_skips_failures_and_stops_at_success() {
+		assert_eq!(run("heard_noise, sees_player, wander", &mut tree_world(true)), "succeeded");
+	}
+
+	#[test]
+	fn tree_brain_waits_when_nothing_acts() {
+		let mut world = tree_world(false);
+		let monster = world.actor_at(&Point::new(7, 1)).unwrap();
+		let mut brain = new_brain("tree", None, Some("sequence(sees_player, approach)")).unwrap();
+
+		let chosen_action = brain.act(&Point::new(7, 1), &mut world).expect("a tree brain always acts");
+		perform(&chosen_action, &monster, &mut world);
+
+		assert!(world.actors.get(&monster).unwrap().get_position() == &Point::new(7, 1));
+	}
+
+	#[test]
+	fn nested_groups() {
+		assert!(parse("attack_adjacent, sequence(sees_player, approach), selector(heard_noise, wait), wander").is_ok());
+		assert!(parse("sequence(heard_noise, selector(shoot, sequence(sees_player, approach)))").is_ok());
+	}
+
+	#[test]
+	fn bad_descriptions() {
+		assert!(parse("sequence(sees_player, approach").is_err());
+		assert!(parse("approach)").is_err());
+		assert!(parse("sequence(sees_player) wait").is_err());
+		assert!(parse("loop(wait)").is_err());
+		assert!(parse("sees_player,,wait").is_err());
+		assert!(parse("teleport").is_err());
+	}
+
+	#[test]
+	fn normalized_descriptions_parse() {
+		let description = "attack_adjacent, sequence( sees_player, approach ), wait";
+		let normalized = normalize(description);
+		assert_eq!(normalized.as_slice(), "attack_adjacent,sequence(sees_player,approach),wait");
+		assert!(parse(normalized.as_slice()).is_ok());
+	}
+}
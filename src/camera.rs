@@ -0,0 +1,50 @@
+use util::Point;
+
+// The part of the world shown on screen. Its top left corner is in world
+// coordinates and it never looks past the edges of the map.
+pub struct Camera {
+	pub x: uint,
+	pub y: uint,
+	pub width: uint,
+	pub height: uint
+}
+
+impl Camera {
+	pub fn new(width: uint, height: uint) -> Camera {
+		Camera {x: 0, y: 0, width: width, height: height}
+	}
+
+	// centers on target, clamped so a map smaller than the view sits at the top left
+	pub fn follow(&mut self, target: &Point, world_width: uint, world_height: uint) {
+		self.x = clamp_axis(target.x, self.width, world_width);
+		self.y = clamp_axis(target.y, self.height, world_height);
+	}
+
+	// screen cell of a world position, None if it is out of view
+	pub fn to_screen(&self, position: &Point) -> Option<(uint, uint)> {
+		if position.x < self.x || position.y < self.y {
+			return None;
+		}
+		let (x, y) = (position.x - self.x, position.y - self.y);
+		if x < self.width && y < self.height { Some((x, y)) } else { None }
+	}
+
+	// world position under a screen cell of the view
+	pub fn to_world(&self, x: uint, y: uint) -> Point {
+		Point::new(self.x + x, self.y + y)
+	}
+}
+
+fn clamp_axis(target: uint, view: uint, world: uint) -> uint {
+	if world <= view {
+		return 0;
+	}
+	let half = view / 2;
+	if target <= half {
+		0
+	} else if target - half + view >= world {
+		world - view
+	} else {
+		target - half
+	}
+}
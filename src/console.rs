@@ -0,0 +1,76 @@
+// Keyboard input from the tcod window, the binary's side of input.
+extern crate tcod;
+
+use tcod::{Console};
+
+use rrl::input::{InputSource, KeyCode};
+
+pub fn check_for_keypress() -> Option<KeyCode> {
+    let flags = tcod::KEY_PRESSED;
+    match Console::check_for_keypress(flags) {
+        Some(key_state) => {
+            // < and > are typed with shift on most layouts, so look at them first
+            match key_state.key {
+                self::tcod::Key::Printable('>') => return Some(KeyCode::Descend),
+                self::tcod::Key::Printable('<') => return Some(KeyCode::Ascend),
+                _                               => {}
+            }
+
+            if key_state.shift || key_state.left_ctrl {
+                Some(KeyCode::ToggleAim)
+                
+            } else {
+                match key_state.key {
+                    self::tcod::Key::Special(tcod::KeyCode::Up)     => Some(KeyCode::Up),
+                    self::tcod::Key::Special(tcod::KeyCode::Down)   => Some(KeyCode::Down),
+                    self::tcod::Key::Special(tcod::KeyCode::Left)   => Some(KeyCode::Left),
+                    self::tcod::Key::Special(tcod::KeyCode::Right)  => Some(KeyCode::Right),
+                    self::tcod::Key::Special(tcod::KeyCode::Escape) => Some(KeyCode::Escape),
+                    self::tcod::Key::Printable('w')                 => Some(KeyCode::Wait),
+                    _                                               => None
+                } 
+            }
+        },
+        None => {
+             None
+        }
+    }
+
+}
+
+pub struct ConsoleInput;
+
+impl InputSource for ConsoleInput {
+    fn next_key(&mut self) -> Option<KeyCode> {
+        check_for_keypress()
+    }
+}
+
+pub fn wait_for_any_key() {
+    let mut pressed = false; 
+    while !pressed {
+        let keypress = Console::wait_for_keypress(true);
+        if keypress.pressed {
+            pressed = true;
+        }
+    }
+    
+}
+
+pub enum TitleChoice {
+    NewGame,
+    Continue
+}
+
+// 'c' continues the saved game, any other key starts a new one
+pub fn wait_for_title_choice() -> TitleChoice {
+    loop {
+        let keypress = Console::wait_for_keypress(true);
+        if keypress.pressed {
+            match keypress.key {
+                self::tcod::Key::Printable('c') => return TitleChoice::Continue,
+                _                               => return TitleChoice::NewGame
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use util::Point;
+use pathfinding::{PathGrid, Node, neighbours};
+
+use std::collections::BinaryHeap;
+
+// Distance from every cell to the nearest goal, in step costs. Walking to the
+// lowest neighbour leads to a goal from anywhere, without searching.
+pub struct DijkstraMap {
+	width: uint,
+	height: uint,
+	values: Vec<Vec<Option<int>>>
+}
+
+impl DijkstraMap {
+	pub fn toward<G: PathGrid>(grid: &G, goals: &[Point]) -> DijkstraMap {
+		let (width, height) = grid.size();
+		let mut seeds = Vec::from_elem(height, Vec::from_elem(width, None));
+		for goal in goals.iter() {
+			seeds[goal.y][goal.x] = Some(0);
+		}
+		DijkstraMap::relax(grid, seeds)
+	}
+
+	// Leads away from the goals of this map. Plain negated distances would run
+	// into the nearest dead end, scaling them and relaxing again lets the way out
+	// past the goals win when it leads further.
+	pub fn fleeing<G: PathGrid>(&self, grid: &G) -> DijkstraMap {
+		let mut seeds = Vec::from_elem(self.height, Vec::from_elem(self.width, None));
+		for y in range(0, self.height) {
+			for x in range(0, self.width) {
+				seeds[y][x] = self.values[y][x].map(|value| -value * 12 / 10);
+			}
+		}
+		DijkstraMap::relax(grid, seeds)
+	}
+
+	// None if no goal can be reached from p
+	pub fn value(&self, p: &Point) -> Option<int> {
+		if p.x >= self.width || p.y >= self.height {
+			return None;
+		}
+		self.values[p.y][p.x]
+	}
+
+	// neighbours of from that are lower than from itself, lowest first. The
+	// first one is the quickest way, the others go around whatever is on it.
+	pub fn downhill(&self, from: &Point) -> Vec<Point> {
+		let here = match self.value(from) {
+			Some(value) => value,
+			None => return Vec::new()
+		};
+		let mut steps: Vec<(int, Point)> = Vec::new();
+		for next in neighbours(from, self.width, self.height, false).into_iter() {
+			if let Some(value) = self.value(&next) {
+				if value < here {
+					steps.push((value, next));
+				}
+			}
+		}
+		steps.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+		steps.into_iter().map(|(_, p)| p).collect()
+	}
+
+	fn relax<G: PathGrid>(grid: &G, seeds: Vec<Vec<Option<int>>>) -> DijkstraMap {
+		let (width, height) = grid.size();
+		let mut values = seeds;
+		let mut open = BinaryHeap::new();
+		for y in range(0, height) {
+			for x in range(0, width) {
+				if let Some(value) = values[y][x] {
+					open.push(Node {priority: value, cost: value, x: x, y: y});
+				}
+			}
+		}
+
+		while let Some(node) = open.pop() {
+			// already reached more cheaply
+			if values[node.y][node.x].map_or(false, |value| value < node.cost) {
+				continue;
+			}
+			// monsters move in the four directions only
+			for next in neighbours(&Point::new(node.x, node.y), width, height, false).into_iter() {
+				let step = match grid.step_cost(&next) {
+					Some(step) => step as int,
+					None => continue
+				};
+				let value = node.cost + step;
+				if values[next.y][next.x].map_or(true, |known| value < known) {
+					values[next.y][next.x] = Some(value);
+					open.push(Node {priority: value, cost: value, x: next.x, y: next.y});
+				}
+			}
+		}
+		DijkstraMap {width: width, height: height, values: values}
+	}
+}
+
+// The maps every monster shares, built for one player position. The world
+// throws them away when the level changes, see World::nav_maps.
+pub struct NavMaps {
+	pub player_position: Point,
+	pub to_player: DijkstraMap,
+	pub from_player: DijkstraMap,
+	// where gunners that ran dry restock, see RangedBrain
+	pub to_ammo: DijkstraMap
+}
+
+impl NavMaps {
+	pub fn build<G: PathGrid>(grid: &G, player_position: &Point, ammo: &[Point]) -> NavMaps {
+		let to_player = DijkstraMap::toward(grid, &[player_position.clone()]);
+		let from_player = to_player.fleeing(grid);
+		NavMaps {
+			player_position: player_position.clone(),
+			to_player: to_player,
+			from_player: from_player,
+			to_ammo: DijkstraMap::toward(grid, ammo)
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::DijkstraMap;
+	use util::Point;
+	use pathfinding::test::TestGrid;
+
+	// the cells downhill leads along from start, until nothing is lower
+	fn walk_downhill(map: &DijkstraMap, start: &Point) -> Vec<Point> {
+		let mut walked = Vec::new();
+		let mut current = start.clone();
+		loop {
+			match map.downhill(&current).into_iter().next() {
+				Some(next) => {
+					walked.push(next.clone());
+					current = next;
+				},
+				None => return walked
+			}
+		}
+	}
+
+	#[test]
+	fn values_grow_away_from_the_goal() {
+		let grid = TestGrid::new(&["....#."]);
+		let map = DijkstraMap::toward(&grid, &[Point::new(0, 0)]);
+		assert_eq!(map.value(&Point::new(0, 0)), Some(0));
+		for x in range(1u, 4) {
+			assert!(map.value(&Point::new(x, 0)) > map.value(&Point::new(x - 1, 0)));
+		}
+		// walled off
+		assert_eq!(map.value(&Point::new(4, 0)), None);
+		assert_eq!(map.value(&Point::new(5, 0)), None);
+	}
+
+	#[test]
+	fn downhill_leads_to_the_goal() {
+		let grid = TestGrid::new(&[
+			"......",
+			".####.",
+			".#..#.",
+			".#.##.",
+			"......"]);
+		let goal = Point::new(3, 2);
+		let map = DijkstraMap::toward(&grid, &[goal.clone()]);
+		let walked = walk_downhill(&map, &Point::new(5, 0));
+		assert!(walked.last() == Some(&goal));
+		// the shortest way is 10 steps, around the bottom
+		assert_eq!(walked.len(), 10);
+	}
+
+	#[test]
+	fn fleeing_runs_past_the_player_out_of_a_dead_end() {
+		// the player is at x 3, the corridor behind the monster at x 2 ends
+		// after two cells but goes on for twenty past the player
+		let grid = TestGrid::new(&["........................"]);
+		let player = Point::new(3, 0);
+		let flee = DijkstraMap::toward(&grid, &[player.clone()]).fleeing(&grid);
+
+		let walked = walk_downhill(&flee, &Point::new(2, 0));
+		assert!(walked[0] == player);
+		assert!(walked.last() == Some(&Point::new(23, 0)));
+	}
+}
@@ -0,0 +1,141 @@
+use util::Point;
+use actor::Actor;
+use world::{World, CellType};
+use generator;
+
+use std::mem;
+
+// the deepest level has no stairs down
+pub static MAX_DEPTH: uint = 5;
+
+// A level the player has left. The actors keep their positions and state and
+// are put back as they were when the player returns.
+pub struct Level {
+	pub depth: uint,
+	pub cell_types: Vec<Vec<CellType>>,
+	pub explored: Vec<Vec<bool>>,
+	pub actors: Vec<Actor>
+}
+
+// Moves the player to the level at depth, generating it on the first visit.
+// The player arrives on the stairs leading back to where it came from.
+pub fn travel(world: &mut World, depth: uint) {
+	let from = world.depth;
+	let level = store_level(world);
+	world.invalidate_nav();
+	world.levels.push(level);
+	world.depth = depth;
+
+	let levels = mem::replace(&mut world.levels, Vec::new());
+	let (mut visited, others): (Vec<Level>, Vec<Level>) = levels.into_iter().partition(|level| level.depth == depth);
+	world.levels = others;
+
+	let entrance = match visited.pop() {
+		Some(level) => {
+			restore_level(world, level);
+			None
+		},
+		None => Some(generator::generate_level(world))
+	};
+
+	let arrival = if depth > from { CellType::StairsUp } else { CellType::StairsDown };
+	let stairs = find_cell(world, &arrival).or(entrance).unwrap_or(Point::new(0, 0));
+	let position = free_spot_near(world, &stairs);
+	let player = world.player.clone();
+	world.set_actor_position(&player, &position);
+
+	let message = if depth > from {
+		format!("You descend to depth {}", depth)
+	} else {
+		format!("You climb up to depth {}", depth)
+	};
+	world.add_message(message.as_slice());
+}
+
+// cell types and explored flags of the current level, row by row
+pub fn snapshot_cells(world: &World) -> (Vec<Vec<CellType>>, Vec<Vec<bool>>) {
+	let mut cell_types = Vec::with_capacity(world.height);
+	let mut explored = Vec::with_capacity(world.height);
+	for y in range(0, world.height) {
+		let mut type_row = Vec::with_capacity(world.width);
+		let mut explored_row = Vec::with_capacity(world.width);
+		for x in range(0, world.width) {
+			let cell = world.get_cell(x, y);
+			type_row.push(cell.cell_type.clone());
+			explored_row.push(cell.explored);
+		}
+		cell_types.push(type_row);
+		explored.push(explored_row);
+	}
+	(cell_types, explored)
+}
+
+// Takes everything but the player out of the world. The player is lifted off the
+// grid too, it gets a new position on the level it arrives at.
+fn store_level(world: &mut World) -> Level {
+	let player = world.player.clone();
+	let (cell_types, explored) = snapshot_cells(world);
+
+	let mut actors = Vec::new();
+	for id in world.actors.ids().iter() {
+		if *id != player {
+			actors.push(world.actors.remove(id).unwrap());
+		}
+	}
+	if let Some(player_actor) = world.actors.get_mut(&player) {
+		player_actor.position = None;
+	}
+
+	for y in range(0, world.height) {
+		for x in range(0, world.width) {
+			world.grid[y][x].clear();
+		}
+	}
+
+	Level {depth: world.depth, cell_types: cell_types, explored: explored, actors: actors}
+}
+
+fn restore_level(world: &mut World, level: Level) {
+	for y in range(0, world.height) {
+		for x in range(0, world.width) {
+			world.grid[y][x].cell_type = level.cell_types[y][x].clone();
+			world.grid[y][x].explored = level.explored[y][x];
+		}
+	}
+	for actor in level.actors.into_iter() {
+		world.restore_actor(actor);
+	}
+}
+
+fn find_cell(world: &World, cell_type: &CellType) -> Option<Point> {
+	for y in range(0, world.height) {
+		for x in range(0, world.width) {
+			if world.get_cell(x, y).cell_type == *cell_type {
+				return Some(Point::new(x, y));
+			}
+		}
+	}
+	None
+}
+
+// p if nothing stands there, otherwise the first empty walkable neighbour.
+// Monsters may have wandered onto the stairs while the player was away.
+fn free_spot_near(world: &World, p: &Point) -> Point {
+	if world.actor_at(p).is_none() {
+		return p.clone();
+	}
+	for dy in range(-1i, 2) {
+		for dx in range(-1i, 2) {
+			let x = p.x as int + dx;
+			let y = p.y as int + dy;
+			if x < 0 || y < 0 {
+				continue;
+			}
+			let candidate = Point::new(x as uint, y as uint);
+			if world.is_walkable(&candidate) && world.actor_at(&candidate).is_none() {
+				return candidate;
+			}
+		}
+	}
+	p.clone()
+}
@@ -0,0 +1,228 @@
+use util::{Point, Direction};
+use actor::Actor;
+use arena::ActorId;
+
+use std::cell::{Cell, RefCell};
+use std::collections::RingBuf;
+use std::rc::Rc;
+
+// lines the message log keeps
+static LOG_LINES: uint = 3;
+
+// Snapshot of the actor an event is about. Events outlive the action that
+// produced them, so they don't hold on to the actor itself.
+#[deriving(Clone)]
+pub struct ActorInfo {
+	pub id: ActorId,
+	pub name: String,
+	pub position: Option<Point>,
+	pub is_player: bool
+}
+
+impl ActorInfo {
+	pub fn of(id: &ActorId, actor: &Actor) -> ActorInfo {
+		ActorInfo {
+			id: id.clone(),
+			name: actor.name.clone(),
+			position: actor.position.clone(),
+			is_player: actor.is_player
+		}
+	}
+}
+
+#[deriving(Clone, PartialEq)]
+pub enum DamageKind {
+	Melee,
+	Projectile,
+	Lava
+}
+
+#[deriving(Clone)]
+pub enum GameEvent {
+	// attacker (none for terrain), target, damage dealt
+	ActorDamaged(Option<ActorInfo>, ActorInfo, int, DamageKind),
+	// victim, killer
+	ActorDied(ActorInfo, Option<ActorInfo>),
+	// picker, item
+	ItemPickedUp(ActorInfo, ActorInfo),
+	// shooter, direction
+	ProjectileFired(ActorInfo, Direction),
+	// spawned actor, spawner
+	ActorSpawned(ActorInfo, ActorInfo),
+	// actor at its new position, old position
+	ActorMoved(ActorInfo, Point),
+	// a monster lost its nerve
+	ActorFled(ActorInfo),
+	// a monster that fled is coming back with others
+	ActorRallied(ActorInfo),
+	// opener, door
+	DoorOpened(ActorInfo, Point),
+	// shooter, where the rubble was
+	RubbleCleared(ActorInfo, Point)
+}
+
+impl GameEvent {
+	// line for the message log, if the event is worth one
+	pub fn describe(&self) -> Option<String> {
+		match *self {
+			GameEvent::ActorDamaged(Some(ref attacker), ref target, _, DamageKind::Melee) => {
+				Some(format!("{} attacks {}", attacker.name, target.name))
+			},
+			GameEvent::ActorDamaged(Some(ref attacker), ref target, _, DamageKind::Projectile) => {
+				Some(format!("{} fires at {}", attacker.name, target.name))
+			},
+			GameEvent::ActorDamaged(_, ref target, _, DamageKind::Lava) => {
+				Some(format!("{} is burned by lava", target.name))
+			},
+			GameEvent::ActorDamaged(None, _, _, _) => None,
+			GameEvent::ActorDied(ref victim, _) => Some(format!("{} dies", victim.name)),
+			GameEvent::ItemPickedUp(_, ref item) => Some(format!("Picked up {}", item.name)),
+			GameEvent::ProjectileFired(..) => None,
+			GameEvent::ActorSpawned(..) => None,
+			GameEvent::ActorMoved(..) => None,
+			GameEvent::ActorFled(ref actor) => Some(format!("{} flees!", actor.name)),
+			GameEvent::ActorRallied(ref actor) => Some(format!("{} comes back with its pack", actor.name)),
+			GameEvent::DoorOpened(ref opener, _) if opener.is_player => Some("The door opens".to_string()),
+			GameEvent::DoorOpened(..) => None,
+			GameEvent::RubbleCleared(..) => Some("The rubble crumbles".to_string())
+		}
+	}
+}
+
+pub trait EventListener {
+	fn on_event(&mut self, event: &GameEvent);
+}
+
+// handed out by subscribe, to unsubscribe with
+#[deriving(Clone, PartialEq)]
+pub struct ListenerId(uint);
+
+pub struct EventBus {
+	listeners: Vec<(ListenerId, Box<EventListener + 'static>)>,
+	next_id: uint
+}
+
+impl EventBus {
+	pub fn new() -> EventBus {
+		EventBus {listeners: Vec::new(), next_id: 0}
+	}
+
+	pub fn subscribe(&mut self, listener: Box<EventListener + 'static>) -> ListenerId {
+		let id = ListenerId(self.next_id);
+		self.next_id += 1;
+		self.listeners.push((id.clone(), listener));
+		id
+	}
+
+	pub fn unsubscribe(&mut self, id: &ListenerId) {
+		self.listeners.retain(|&(ref subscribed, _)| *subscribed != *id);
+	}
+
+	pub fn publish(&mut self, event: &GameEvent) {
+		for &(_, ref mut listener) in self.listeners.iter_mut() {
+			listener.on_event(event);
+		}
+	}
+}
+
+pub struct Statistics {
+	pub shots_fired: uint,
+	pub damage_dealt: int,
+	pub damage_taken: int,
+	pub pickups: uint,
+	pub spawns: uint
+}
+
+// Tallies what happened during a run. The counts are shared so they can be read
+// after the listener has been handed to the bus.
+pub struct StatisticsListener {
+	pub statistics: Rc<RefCell<Statistics>>
+}
+
+impl StatisticsListener {
+	pub fn new() -> StatisticsListener {
+		StatisticsListener {
+			statistics: Rc::new(RefCell::new(Statistics {shots_fired: 0, damage_dealt: 0, damage_taken: 0, pickups: 0, spawns: 0}))
+		}
+	}
+}
+
+impl EventListener for StatisticsListener {
+	fn on_event(&mut self, event: &GameEvent) {
+		let mut statistics = self.statistics.borrow_mut();
+		match *event {
+			GameEvent::ActorDamaged(ref attacker, ref target, damage, _) => {
+				if attacker.as_ref().map_or(false, |attacker| attacker.is_player) {
+					statistics.damage_dealt += damage;
+				}
+				if target.is_player {
+					statistics.damage_taken += damage;
+				}
+			},
+			GameEvent::ProjectileFired(ref shooter, _) => {
+				if shooter.is_player {
+					statistics.shots_fired += 1;
+				}
+			},
+			GameEvent::ItemPickedUp(ref picker, _) => {
+				if picker.is_player {
+					statistics.pickups += 1;
+				}
+			},
+			GameEvent::ActorSpawned(..) => {
+				statistics.spawns += 1;
+			},
+			_ => {}
+		}
+	}
+}
+
+// The message log. The world shows and saves the lines, see World::add_message
+// for lines that aren't about an event.
+pub struct MessageListener {
+	pub messages: Rc<RefCell<RingBuf<String>>>
+}
+
+impl MessageListener {
+	pub fn new() -> MessageListener {
+		MessageListener {messages: Rc::new(RefCell::new(RingBuf::new()))}
+	}
+}
+
+impl EventListener for MessageListener {
+	fn on_event(&mut self, event: &GameEvent) {
+		if let Some(message) = event.describe() {
+			log(&self.messages, message);
+		}
+	}
+}
+
+// adds a line to a message log, dropping the oldest one when it's full
+pub fn log(messages: &Rc<RefCell<RingBuf<String>>>, message: String) {
+	let mut messages = messages.borrow_mut();
+	messages.push_back(message);
+	if messages.len() > LOG_LINES {
+		messages.pop_front();
+	}
+}
+
+// Counts the monsters the player killed.
+pub struct KillListener {
+	pub kills: Rc<Cell<uint>>
+}
+
+impl KillListener {
+	pub fn new() -> KillListener {
+		KillListener {kills: Rc::new(Cell::new(0))}
+	}
+}
+
+impl EventListener for KillListener {
+	fn on_event(&mut self, event: &GameEvent) {
+		if let GameEvent::ActorDied(_, Some(ref killer)) = *event {
+			if killer.is_player {
+				self.kills.set(self.kills.get() + 1);
+			}
+		}
+	}
+}
@@ -0,0 +1,79 @@
+use util::Point;
+use world::World;
+
+// how far the player can see in an open cave
+pub static FOV_RADIUS: uint = 10;
+
+// transforms the first octant into each of the eight around the origin
+static OCTANTS: [[int, ..4], ..8] = [
+	[1, 0, 0, 1],
+	[0, 1, 1, 0],
+	[0, -1, 1, 0],
+	[-1, 0, 0, 1],
+	[-1, 0, 0, -1],
+	[0, -1, -1, 0],
+	[0, 1, -1, 0],
+	[1, 0, 0, -1]
+];
+
+// Cells visible from origin within radius, by recursive shadowcasting.
+// http://www.roguebasin.com/index.php?title=FOV_using_recursive_shadowcasting
+// Walls that are seen are included, so the cave outline shows.
+pub fn compute(world: &World, origin: &Point, radius: uint) -> Vec<Point> {
+	let mut visible = vec![origin.clone()];
+	for octant in OCTANTS.iter() {
+		cast_light(world, origin, radius as int, 1, 1.0, 0.0, octant, &mut visible);
+	}
+	visible
+}
+
+fn cast_light(world: &World, origin: &Point, radius: int, row: int, start_slope: f32, end_slope: f32, octant: &[int, ..4], visible: &mut Vec<Point>) {
+	if start_slope < end_slope {
+		return;
+	}
+
+	let mut start_slope = start_slope;
+	let mut next_start_slope = start_slope;
+
+	for distance in range(row, radius + 1) {
+		let mut blocked = false;
+		let dy = -distance;
+
+		for dx in range(-distance, 1) {
+			let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+			let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+			if start_slope < right_slope {
+				continue;
+			} else if end_slope > left_slope {
+				break;
+			}
+
+			let x = origin.x as int + dx * octant[0] + dy * octant[1];
+			let y = origin.y as int + dx * octant[2] + dy * octant[3];
+			let in_bounds = x >= 0 && y >= 0 && world.is_valid(&Point::new(x as uint, y as uint));
+
+			if in_bounds && dx * dx + dy * dy <= radius * radius {
+				visible.push(Point::new(x as uint, y as uint));
+			}
+
+			let opaque = !in_bounds || world.blocks_sight(&Point::new(x as uint, y as uint));
+			if blocked {
+				if opaque {
+					next_start_slope = right_slope;
+				} else {
+					blocked = false;
+					start_slope = next_start_slope;
+				}
+			} else if opaque && distance < radius {
+				// scan the part of the next row this wall doesn't shadow
+				blocked = true;
+				cast_light(world, origin, radius, distance + 1, start_slope, left_slope, octant, visible);
+				next_start_slope = right_slope;
+			}
+		}
+
+		if blocked {
+			break;
+		}
+	}
+}
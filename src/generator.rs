@@ -1,139 +1,305 @@
 
 use util::Point;
-use actor::Actor;
+use template::Role;
+use dungeon;
+use mapgen;
+use mapgen::MapGenerator;
+use terrain;
+use vault::VaultCell;
 use world::{World, CellType};
 
-use std::rand;
-use std::num::SignedInt;
+use std::rand::Rng;
 
+// a new run: the first level, with the player placed on it
 pub fn generate(world: &mut World) {
 		println!("generate");
 
-		// http://www.roguebasin.com/index.php?title=Cellular_Automata_Method_for_Generating_Random_Cave-Like_Levels#C_Code
-
 		world.cleanup();
-		world.actors.push(*world.player.clone());
 
-		let fill_prob = 40;
-		let generations = 5u;
-		let r1_cutoff = 5u;
-		let r2_cutoff = 2u;
+		// restart the sequence so the same seed always gives the same dungeon
+		let seed = world.seed;
+		world.reseed(seed);
+
+		let entrance = generate_level(world);
+		let player = world.player.clone();
+		world.set_actor_position(&player, &entrance);
+		world.update_fov();
+	}
+
+// Builds a level for world.depth on an empty grid, laid out by a randomly picked
+// map generator, with its stairs and inhabitants, and returns a free spot for the
+// player to start on. Deeper levels get more monsters and spawners, and
+// templates that only appear further down.
+pub fn generate_level(world: &mut World) -> Point {
+	let map_generator = mapgen::pick(&mut world.rng);
+	generate_level_with(world, &*map_generator)
+}
+
+// generate_level with the layout left to the given map generator
+pub fn generate_level_with(world: &mut World, map_generator: &MapGenerator) -> Point {
 
-		let mut grid : Vec<Vec<uint>> = Vec::with_capacity(world.height);
-		let mut grid2 : Vec<Vec<uint>> = Vec::with_capacity(world.height);
+		let mut grid = map_generator.generate(world.width, world.height, &mut world.rng);
+		let (vault_actors, in_vault) = stamp_vaults(world, &mut grid);
+		connect_regions(&mut grid, world.width, world.height, &in_vault);
 
-		// fill grid with random
 		for y in range (0, world.height) {
-			let mut rows:Vec<uint> = Vec::with_capacity(world.width);
-			let mut rows2:Vec<uint> = Vec::with_capacity(world.width);
+			for x in range (0, world.width) {
+				let cell = world.grid.index_mut(&y).index_mut(&x);
+				match grid[y][x] {
+					1 => { cell.cell_type = CellType::Wall },
+					_ => { cell.cell_type = CellType::Floor },
+				}	
+			}
+		}
+
+		terrain::add_features(world, &in_vault);
 
+		// all floor tiles, except the vaults which are already furnished
+		let mut floors : Vec<Point> = Vec::new();
+		for y in range (0, world.height) {
 			for x in range (0, world.width) {
-				let mut cell_type = 0;
-				if x == 0 || y == 0 || x == world.width - 1 || y == world.height -1 {
-					cell_type = 1
-				} else if rand::random::<uint>() % 100 < fill_prob {
-					cell_type = 1;
+				if world.get_cell(x, y).cell_type == CellType::Floor && !in_vault[y][x] {
+					floors.push(Point::new(x,y));
 				}
-				rows.push(cell_type);
-				rows2.push(1);
 			}
-			grid.push(rows);
-			grid2.push(rows2);
+		}
+	
+		// the entrance is on the stairs up, except on the first level
+		let depth = world.depth;
+		let entrance = take_floor(world, &mut floors);
+		if depth > 1 {
+			world.grid[entrance.y][entrance.x].cell_type = CellType::StairsUp;
+		}
+		if depth < dungeon::MAX_DEPTH {
+			let stairs = take_floor(world, &mut floors);
+			world.grid[stairs.y][stairs.x].cell_type = CellType::StairsDown;
+		}
+
+		for &(ref position, ref template) in vault_actors.iter() {
+			if let Some(actor) = world.templates.build(template.as_slice()) {
+				world.add_actor(actor, position.clone());
+			}
 		}
 
-		for _ in range (0, generations) {
-			for y in range (1i, world.height as int - 1) {
-				for x in range (1i, world.width as int - 1) {
-					
-					let mut adjacent_count_r1 = 0u;
-					let mut adjacent_count_r2 = 0u;
-					
-					// the number of tiles within 1 step of p which are walls
-					for yy in range (-1i, 2) {
-						for xx in range (-1i, 2) {
-							let yyy = y + yy;
-							let xxx = x + xx;
-							if grid[yyy as uint][xxx as uint] == 1 {
-							 	adjacent_count_r1 += 1; 
-							} 	
-						}
-					}
-					// p is in the middle of an open space
-					for yy in range (y - 2i, y + 3) {
-						for xx in range (x -2i, x + 3) {
-							
-							// skip p itself
-							if (xx - x).abs() == 2 && (yy - y).abs() == 2 {
-								continue;
-							}
-							
-							if xx >= 0 && xx < world.width as int && yy >= 0 && yy < world.height as int {
-								if grid[yy as uint][xx as uint] == 1 {
-									adjacent_count_r2 += 1;
-								}
-							}
-						}
-					}
+		let extra = depth - 1;
+		place(world, &mut floors, Role::Monster, 10 + extra * 2);
+		place(world, &mut floors, Role::Spawner, 10 + extra * 3);
+		place(world, &mut floors, Role::Item, 10);
 
-					if adjacent_count_r1 >= r1_cutoff || adjacent_count_r2 <= r2_cutoff {
-						grid2[y as uint][x as uint] = 1;
-					} else {
-						grid2[y as uint][x as uint] = 0;
-					}
-				}
+		entrance
+	}
+
+// how many vaults a level tries to fit, and how hard
+static VAULTS_PER_LEVEL: uint = 2;
+static VAULT_ATTEMPTS: uint = 20;
+
+// Stamps vaults into grid where they fit without overlapping each other. Returns
+// the actors the vaults place, and which cells belong to a vault.
+fn stamp_vaults(world: &mut World, grid: &mut Vec<Vec<uint>>) -> (Vec<(Point, String)>, Vec<Vec<bool>>) {
+	let (width, height) = (world.width, world.height);
+	let mut actors = Vec::new();
+	let mut in_vault = Vec::from_elem(height, Vec::from_elem(width, false));
+
+	for _ in range(0, VAULTS_PER_LEVEL) {
+		let vault = match world.vaults.pick(world.depth, &mut world.rng) {
+			Some(vault) => vault,
+			None => break
+		};
+		// the map border stays wall
+		if vault.width() + 2 > width || vault.height() + 2 > height {
+			continue;
+		}
+
+		for _ in range(0, VAULT_ATTEMPTS) {
+			let left = world.rng.gen_range(1u, width - vault.width());
+			let top = world.rng.gen_range(1u, height - vault.height());
+
+			let overlaps = range(top, top + vault.height()).any(|y| range(left, left + vault.width()).any(|x| in_vault[y][x]));
+			if overlaps {
+				continue;
 			}
 
-			for y in range (0, world.height) {
-				for x in range (0, world.width) {
-					grid[y][x] = grid2[y][x];
+			for (dy, row) in vault.rows.iter().enumerate() {
+				for (dx, cell) in row.iter().enumerate() {
+					let (x, y) = (left + dx, top + dy);
+					match *cell {
+						VaultCell::Untouched => continue,
+						VaultCell::Wall => { grid[y][x] = mapgen::WALL; },
+						VaultCell::Floor => { grid[y][x] = mapgen::FLOOR; },
+						VaultCell::Actor(ref template) => {
+							grid[y][x] = mapgen::FLOOR;
+							actors.push((Point::new(x, y), template.clone()));
+						}
+					}
+					in_vault[y][x] = true;
 				}
 			}
+			break;
 		}
+	}
+	(actors, in_vault)
+}
 
-		// all floor tiles
-		let mut floors : Vec<Point> = Vec::new();
+// removes and returns a random tile from floors
+fn take_floor(world: &mut World, floors: &mut Vec<Point>) -> Point {
+	let index = world.rng.gen_range(0u, floors.len());
+	let floor = floors[index].clone();
+	floors.remove(index);
+	floor
+}
 
-		for y in range (0, world.height) {
-			for x in range (0, world.width) {
-				let cell = world.grid.index_mut(&y).index_mut(&x);
-				match grid[y][x] {
-					1 => { cell.cell_type = CellType::Wall },
-					_ => { 
-							cell.cell_type = CellType::Floor; 
-							floors.push(Point::new(x,y)) },
-				}	
+// puts count actors of the given role on random free floor tiles, picking
+// their templates by spawn weight among those allowed at this depth
+fn place(world: &mut World, floors: &mut Vec<Point>, role: Role, count: uint) {
+	for _ in range(0, count) {
+		if floors.is_empty() {
+			return;
+		}
+		let actor = match world.templates.pick(role.clone(), world.depth, &mut world.rng) {
+			Some(template) => template.build(),
+			None => return
+		};
+		let position = take_floor(world, floors);
+		world.add_actor(actor, position);
+	}
+}
+
+// pockets smaller than this are filled in rather than tunnelled to
+static MIN_REGION_SIZE: uint = 10;
+
+// Makes every floor tile of grid reachable from every other. Small pockets are
+// walled up, the rest get a tunnel to the largest cave. Pockets with a cell
+// marked in keep, e.g. a vault, are always tunnelled to.
+pub fn connect_regions(grid: &mut Vec<Vec<uint>>, width: uint, height: uint, keep: &Vec<Vec<bool>>) {
+	let mut regions = find_regions(grid, width, height);
+	if regions.is_empty() {
+		return;
+	}
+
+	// largest first, it's what everything else connects to
+	regions.sort_by(|a, b| b.len().cmp(&a.len()));
+	let mut connected = regions[0].clone();
+
+	for region in regions.iter().skip(1) {
+		if region.len() < MIN_REGION_SIZE && !region.iter().any(|p| keep[p.y][p.x]) {
+			for p in region.iter() {
+				grid[p.y][p.x] = mapgen::WALL;
 			}
+			continue;
 		}
-	
-		// random start positon
-		let index = rand::random::<uint>() % floors.len();
-		{
-			let mut p = world.player.borrow_mut();
-			p.set_position(Point {x: floors[index].x, y: floors[index].y});
-			floors.remove(index);
+
+		let (from, to) = closest_pair(region, &connected);
+		mapgen::carve_tunnel(grid, &from, &to);
+		connected.push_all(region.as_slice());
+	}
+}
+
+// 4-connected groups of floor tiles, the way actors walk
+fn find_regions(grid: &Vec<Vec<uint>>, width: uint, height: uint) -> Vec<Vec<Point>> {
+	let mut seen = Vec::from_elem(height, Vec::from_elem(width, false));
+	let mut regions = Vec::new();
+
+	for y in range(0, height) {
+		for x in range(0, width) {
+			if grid[y][x] != mapgen::FLOOR || seen[y][x] {
+				continue;
+			}
+
+			let mut region = Vec::new();
+			let mut open = vec![Point::new(x, y)];
+			seen[y][x] = true;
+			while let Some(p) = open.pop() {
+				let neighbours = [(p.x, p.y - 1), (p.x, p.y + 1), (p.x - 1, p.y), (p.x + 1, p.y)];
+				for &(nx, ny) in neighbours.iter() {
+					if nx < width && ny < height && grid[ny][nx] == mapgen::FLOOR && !seen[ny][nx] {
+						seen[ny][nx] = true;
+						open.push(Point::new(nx, ny));
+					}
+				}
+				region.push(p);
+			}
+			regions.push(region);
 		}
+	}
+	regions
+}
 
-		let enemies_count = 10u;
-		for _ in range(0, enemies_count) {
-			let index = rand::random::<uint>() % floors.len();
-			world.add_actor(Actor::kobold(), Point{x: floors[index].x, y: floors[index].y});
-			floors.remove(index);			
+fn closest_pair(region: &Vec<Point>, connected: &Vec<Point>) -> (Point, Point) {
+	let mut best = (region[0].clone(), connected[0].clone());
+	let mut best_distance = region[0].distance_to(&connected[0]);
+	for a in region.iter() {
+		for b in connected.iter() {
+			let distance = a.distance_to(b);
+			if distance < best_distance {
+				best_distance = distance;
+				best = (a.clone(), b.clone());
+			}
 		}
+	}
+	best
+}
 
-		let generators_count = 10u;
-		for _ in range(0, generators_count) {
-			let index = rand::random::<uint>() % floors.len();
-			world.add_actor(Actor::kobold_generator(), Point{x: floors[index].x, y: floors[index].y});
-			floors.remove(index);			
+#[cfg(test)]
+mod test {
+	use super::generate_level_with;
+	use util::Point;
+	use world::World;
+	use dungeon;
+	use mapgen::{MapGenerator, CaveGenerator, BspGenerator, DrunkardGenerator};
+
+	// every cell that can be walked to from start, doors included
+	fn reachable(world: &World, start: &Point) -> Vec<Vec<bool>> {
+		let mut seen = Vec::from_elem(world.height, Vec::from_elem(world.width, false));
+		let mut open = vec![start.clone()];
+		seen[start.y][start.x] = true;
+		while let Some(p) = open.pop() {
+			let neighbours = [(p.x, p.y - 1), (p.x, p.y + 1), (p.x - 1, p.y), (p.x + 1, p.y)];
+			for &(nx, ny) in neighbours.iter() {
+				if nx < world.width && ny < world.height && !seen[ny][nx] && world.get_cell(nx, ny).cell_type.is_passable() {
+					seen[ny][nx] = true;
+					open.push(Point::new(nx, ny));
+				}
+			}
 		}
+		seen
+	}
+
+	// every level of a few seeds, vaults and terrain included, has all of its
+	// actors within reach of the entrance
+	fn check_reachable(map_generator: &MapGenerator) {
+		for seed in range(1u32, 6) {
+			for depth in range(1u, dungeon::MAX_DEPTH + 1) {
+				let mut world = World::new(120, 80);
+				world.reseed(seed);
+				world.cleanup();
+				world.depth = depth;
+				let entrance = generate_level_with(&mut world, map_generator);
 
-		let ammo_crates_count = 10u;
-		for _ in range(0, ammo_crates_count) {
-			let index = rand::random::<uint>() % floors.len();
-			world.add_actor(Actor::ammo_crate(), Point{x: floors[index].x, y: floors[index].y});
-			floors.remove(index);			
+				let seen = reachable(&world, &entrance);
+				for id in world.actors.ids().iter() {
+					if *id == world.player {
+						continue;
+					}
+					let actor = world.actors.get(id).unwrap();
+					let p = actor.get_position();
+					assert!(seen[p.y][p.x], "seed {} depth {}: {} at {}, {} can't be reached", seed, depth, actor.name, p.x, p.y);
+				}
+			}
 		}
+	}
 
+	#[test]
+	fn cave_levels_are_connected() {
+		check_reachable(&CaveGenerator::new());
+	}
 
+	#[test]
+	fn bsp_levels_are_connected() {
+		check_reachable(&BspGenerator::new());
+	}
 
-	}
\ No newline at end of file
+	#[test]
+	fn drunkard_levels_are_connected() {
+		check_reachable(&DrunkardGenerator::new());
+	}
+}
@@ -0,0 +1,97 @@
+use world::World;
+use event::StatisticsListener;
+
+pub struct Outcome {
+	pub ticks: uint,
+	pub kills: uint,
+	pub shots_fired: uint,
+	pub damage_taken: int,
+	pub player_alive: bool
+}
+
+// Ticks the world without a renderer until the player dies, a scripted input runs
+// dry or max_ticks is reached. The world must already be generated and driven by
+// a non-console input source.
+pub fn run(world: &mut World, max_ticks: uint) -> Outcome {
+	let listener = StatisticsListener::new();
+	let statistics = listener.statistics.clone();
+	let subscription = world.events.subscribe(box listener);
+
+	let mut ticks = 0u;
+	while ticks < max_ticks && !world.is_game_over() && !is_out_of_input(world) {
+		world.tick();
+		ticks += 1;
+	}
+	// the counts only cover this run
+	world.events.unsubscribe(&subscription);
+
+	let statistics = statistics.borrow();
+	Outcome {
+		ticks: ticks,
+		kills: world.player_state.kills.get(),
+		shots_fired: statistics.shots_fired,
+		damage_taken: statistics.damage_taken,
+		player_alive: !world.is_game_over()
+	}
+}
+
+pub fn is_out_of_input(world: &World) -> bool {
+	world.awaiting_input && world.input.is_exhausted()
+}
+
+#[cfg(test)]
+mod test {
+	use super::run;
+	use world::World;
+	use input::RandomInput;
+	use generator;
+
+	static TICKS: uint = 3000;
+
+	fn random_world(seed: u32) -> World {
+		let mut world = World::with_input(120, 80, box RandomInput::new(seed));
+		world.reseed(seed);
+		generator::generate(&mut world);
+		world
+	}
+
+	#[test]
+	fn random_runs_stay_consistent() {
+		for seed in range(1u32, 6) {
+			let mut world = random_world(seed);
+			let outcome = run(&mut world, TICKS);
+
+			assert!(outcome.ticks > 0 && outcome.ticks <= TICKS);
+			assert_eq!(outcome.player_alive, !world.is_game_over());
+			assert!(outcome.damage_taken >= 0);
+			if outcome.player_alive {
+				assert_eq!(outcome.ticks, TICKS);
+				let player_position = world.get_player_position();
+				assert!(world.is_valid(&player_position));
+			}
+			for id in world.actors.ids().iter() {
+				if let Some(ref p) = world.actors.get(id).unwrap().position {
+					assert!(world.is_valid(p));
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn same_seed_same_run() {
+		let mut first = random_world(42);
+		let mut second = random_world(42);
+		let a = run(&mut first, TICKS);
+		let b = run(&mut second, TICKS);
+
+		assert_eq!(a.ticks, b.ticks);
+		assert_eq!(a.kills, b.kills);
+		assert_eq!(a.shots_fired, b.shots_fired);
+		assert_eq!(a.damage_taken, b.damage_taken);
+		assert_eq!(a.player_alive, b.player_alive);
+		assert!(first.input_log == second.input_log);
+		if a.player_alive {
+			assert!(first.get_player_position() == second.get_player_position());
+		}
+	}
+}
@@ -1,7 +1,9 @@
-extern crate tcod;
+use std::collections::RingBuf;
+use std::rand::Rng;
 
-use tcod::{Console};
+use util::GameRng;
 
+#[deriving(Clone, PartialEq)]
 pub enum KeyCode {
     // Arrow keys
     Up,
@@ -12,41 +14,102 @@ pub enum KeyCode {
     ToggleAim,
     Wait,
     Escape,
+
+    // stairs
+    Descend,
+    Ascend,
+}
+
+impl KeyCode {
+    // short names used by replay and save files
+    pub fn token(&self) -> &'static str {
+        match *self {
+            KeyCode::Up        => "u",
+            KeyCode::Down      => "d",
+            KeyCode::Left      => "l",
+            KeyCode::Right     => "r",
+            KeyCode::ToggleAim => "a",
+            KeyCode::Wait      => "w",
+            KeyCode::Escape    => "q",
+            KeyCode::Descend   => ">",
+            KeyCode::Ascend    => "<",
+        }
+    }
+
+    pub fn from_token(token: &str) -> Option<KeyCode> {
+        match token {
+            "u" => Some(KeyCode::Up),
+            "d" => Some(KeyCode::Down),
+            "l" => Some(KeyCode::Left),
+            "r" => Some(KeyCode::Right),
+            "a" => Some(KeyCode::ToggleAim),
+            "w" => Some(KeyCode::Wait),
+            "q" => Some(KeyCode::Escape),
+            ">" => Some(KeyCode::Descend),
+            "<" => Some(KeyCode::Ascend),
+            _   => None
+        }
+    }
+}
+
+// Where the player brain gets its keys from. The windowed game reads the
+// tcod console, headless runs feed keys from a script or generate them.
+pub trait InputSource {
+    fn next_key(&mut self) -> Option<KeyCode>;
+
+    // true once a finite source has no keys left
+    fn is_exhausted(&self) -> bool {
+        false
+    }
 }
 
-pub fn check_for_keypress() -> Option<KeyCode> {
-    let flags = tcod::KEY_PRESSED;
-    match Console::check_for_keypress(flags) {
-        Some(key_state) => {
-            if key_state.shift || key_state.left_ctrl {
-                Some(KeyCode::ToggleAim)
-                
-            } else {
-                match key_state.key {
-                    self::tcod::Key::Special(tcod::KeyCode::Up)     => Some(KeyCode::Up),
-                    self::tcod::Key::Special(tcod::KeyCode::Down)   => Some(KeyCode::Down),
-                    self::tcod::Key::Special(tcod::KeyCode::Left)   => Some(KeyCode::Left),
-                    self::tcod::Key::Special(tcod::KeyCode::Right)  => Some(KeyCode::Right),
-                    self::tcod::Key::Special(tcod::KeyCode::Escape) => Some(KeyCode::Escape),
-                    self::tcod::Key::Printable('w')                 => Some(KeyCode::Wait),
-                    _                                               => None
-                } 
-            }
-        },
-        None => {
-             None
+pub struct ScriptedInput {
+    keys: RingBuf<KeyCode>
+}
+
+impl ScriptedInput {
+    pub fn new(keys: Vec<KeyCode>) -> ScriptedInput {
+        let mut queue = RingBuf::new();
+        for key in keys.into_iter() {
+            queue.push_back(key);
         }
+        ScriptedInput {keys: queue}
+    }
+
+}
+
+impl InputSource for ScriptedInput {
+    fn next_key(&mut self) -> Option<KeyCode> {
+        self.keys.pop_front()
     }
 
+    fn is_exhausted(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+// Mashes keys at random, for soak testing the simulation.
+pub struct RandomInput {
+    rng: GameRng
+}
+
+impl RandomInput {
+    pub fn new(seed: u32) -> RandomInput {
+        RandomInput {rng: GameRng::new(seed)}
+    }
 }
 
-pub fn wait_for_any_key() {
-    let mut pressed = false; 
-    while !pressed {
-        let keypress = Console::wait_for_keypress(true);
-        if keypress.pressed {
-            pressed = true;
+impl InputSource for RandomInput {
+    fn next_key(&mut self) -> Option<KeyCode> {
+        match self.rng.gen_range(0u, 8) {
+            0 => Some(KeyCode::Up),
+            1 => Some(KeyCode::Down),
+            2 => Some(KeyCode::Left),
+            3 => Some(KeyCode::Right),
+            4 => Some(KeyCode::ToggleAim),
+            5 => Some(KeyCode::Descend),
+            6 => Some(KeyCode::Ascend),
+            _ => Some(KeyCode::Wait)
         }
     }
-    
 }
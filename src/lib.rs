@@ -0,0 +1,31 @@
+// The game simulation, everything but the window. The rrl binary draws it with
+// tcod, headless runs and tests use it without a display.
+extern crate serialize;
+
+pub mod util;
+pub mod actor;
+pub mod action;
+pub mod world;
+pub mod generator;
+pub mod mapgen;
+pub mod camera;
+pub mod pathfinding;
+pub mod dijkstra;
+pub mod perception;
+pub mod morale;
+pub mod behaviour;
+pub mod tactics;
+pub mod terrain;
+pub mod input;
+pub mod headless;
+pub mod save;
+pub mod replay;
+pub mod scheduler;
+pub mod event;
+pub mod system;
+pub mod arena;
+pub mod fov;
+pub mod dungeon;
+pub mod template;
+pub mod vault;
+pub mod mapfile;
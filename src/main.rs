@@ -1,13 +1,16 @@
 extern crate tcod;
+extern crate rrl;
 use tcod::{Console};
 
-mod util;
-mod actor;
-mod action;
-mod world;
-mod generator;
+use rrl::{world, generator, headless, save, replay, template, vault, mapfile, input};
+
+use std::os;
+use std::rand;
+use std::io::timer;
+use std::time::Duration;
+
 mod renderer;
-mod input;
+mod console;
 
 enum State {
 	Title,
@@ -20,23 +23,97 @@ fn main() {
 
 	let mut state = State::Title;
 
+	// size of the map view on screen
 	let w = 80;
 	let h = 50;
+	// size of the world, the camera scrolls over it
+	let map_w = 120;
+	let map_h = 80;
+
+	let args = os::args();
+	// a fixed seed replays the same caves every run, e.g. for a daily seed
+	let fixed_seed = parse_arg(&args, "--seed").map(|seed| seed as u32);
+
+	let headless = args.iter().any(|a| a.as_slice() == "--headless");
+	let turns = parse_arg(&args, "--turns").unwrap_or(10000);
+
+	let data = match GameData::load() {
+		Ok(data) => data,
+		Err(e) => {
+			println!("could not load game data: {}", e);
+			return;
+		}
+	};
+
+	if let Some(export_file) = arg_value(&args, "--export-map") {
+		let seed = fixed_seed.unwrap_or_else(|| rand::random::<u32>());
+		export_map(map_w, map_h, seed, &Path::new(export_file), data);
+		return;
+	}
+
+	// a hand made first level instead of a generated one
+	let map = match arg_value(&args, "--map") {
+		Some(map_file) => match mapfile::MapData::load(&Path::new(map_file)) {
+			Ok(map) => Some(map),
+			Err(e) => {
+				println!("could not load map: {}", e);
+				return;
+			}
+		},
+		None => None
+	};
+
+	if let Some(replay_file) = arg_value(&args, "--replay") {
+		let replay = match replay::Replay::load(&Path::new(replay_file)) {
+			Ok(replay) => replay,
+			Err(e) => {
+				println!("could not load replay: {}", e);
+				return;
+			}
+		};
+		if headless {
+			run_headless_replay(&replay, turns, data);
+		} else {
+			run_replay(&replay, w, h, data);
+		}
+		return;
+	}
+
+	if headless {
+		let seed = fixed_seed.unwrap_or_else(|| rand::random::<u32>());
+		run_headless(map_w, map_h, seed, turns, arg_value(&args, "--record"), data, &map);
+		return;
+	}
 
 	let mut renderer = renderer::Renderer::new(w, h, "kobold mayhem");
 
-	let mut world = world::World::new(w, h);
-	
+	let mut world = world::World::with_input(map_w, map_h, box console::ConsoleInput);
+	data.install(&mut world);
+	let save_path = Path::new(save::SAVE_FILE);
 
     while !Console::window_closed() {
     	match state {
     		State::Title => {
-    			renderer.draw_title();
-    			input::wait_for_any_key();
+    			let can_continue = save::exists(&save_path);
+    			renderer.draw_title(can_continue, &world.templates);
+    			let choice = console::wait_for_title_choice();
     			state = State::Game;
 
+    			let mut loaded = false;
+    			if let console::TitleChoice::Continue = choice {
+    				if can_continue {
+    					match save::load(&mut world, &save_path) {
+    						Ok(()) => { loaded = true; },
+    						Err(e) => { println!("could not load saved game: {}", e); }
+    					}
+    				}
+    			}
+
     			// generate and draw world once
-    			generator::generate(&mut world);
+    			if !loaded {
+    				let seed = fixed_seed.unwrap_or_else(|| rand::random::<u32>());
+    				start_run(&mut world, seed, &map);
+    			}
 				renderer.draw_world(&world);
     		},
     		State::Game => {
@@ -46,13 +123,153 @@ fn main() {
     			}
     			renderer.draw_world(&world);
 
-    			if game_over {
+    			if world.player_state.quit_requested {
+    				world.player_state.quit_requested = false;
+    				if let Err(e) = save::save(&world, &save_path) {
+    					println!("could not save game: {}", e.desc);
+    				}
+    				state = State::Title;
+    			} else if game_over {
+    				// no resuming a finished run, but keep it around for replaying
+    				save::delete(&save_path);
+    				if let Err(e) = replay::Replay::from_world(&world).save(&Path::new(replay::REPLAY_FILE)) {
+    					println!("could not save replay: {}", e.desc);
+    				}
     				renderer.draw_game_over();
-    				input::wait_for_any_key();
+    				console::wait_for_any_key();
     				state = State::Title;
     			}
     		}
     	}
     	
     }
-}
\ No newline at end of file
+}
+
+// value following a flag, e.g. --replay rrl.replay
+fn arg_value(args: &Vec<String>, flag: &str) -> Option<String> {
+	for i in range(0, args.len()) {
+		if args[i].as_slice() == flag && i + 1 < args.len() {
+			return Some(args[i + 1].clone());
+		}
+	}
+	None
+}
+
+// numeric value following a flag, e.g. --turns 500
+fn parse_arg(args: &Vec<String>, flag: &str) -> Option<uint> {
+	arg_value(args, flag).and_then(|value| value.parse::<uint>())
+}
+
+// Everything read from data/ at startup. Files that are missing fall back to
+// the copies built into the binary.
+struct GameData {
+	templates: template::TemplateRegistry,
+	vaults: vault::VaultRegistry
+}
+
+impl GameData {
+	fn load() -> Result<GameData, String> {
+		let templates_path = Path::new(template::TEMPLATES_FILE);
+		let templates = if templates_path.exists() {
+			try!(template::TemplateRegistry::load(&templates_path))
+		} else {
+			template::TemplateRegistry::builtin()
+		};
+
+		let vaults_path = Path::new(vault::VAULTS_FILE);
+		let vaults = if vaults_path.exists() {
+			try!(vault::VaultRegistry::load(&vaults_path))
+		} else {
+			vault::VaultRegistry::builtin()
+		};
+		try!(vaults.check_templates(&templates));
+
+		Ok(GameData {templates: templates, vaults: vaults})
+	}
+
+	fn install(self, world: &mut world::World) {
+		world.templates = self.templates;
+		world.vaults = self.vaults;
+	}
+}
+
+// a new run from seed, on map if there is one
+fn start_run(world: &mut world::World, seed: u32, map: &Option<mapfile::MapData>) {
+	world.reseed(seed);
+	match *map {
+		Some(ref map) => {
+			if let Err(e) = map.apply(world) {
+				println!("could not use map, generating one instead: {}", e);
+				generator::generate(world);
+			}
+		},
+		None => generator::generate(world)
+	}
+}
+
+fn export_map(w: uint, h: uint, seed: u32, path: &Path, data: GameData) {
+	let mut world = world::World::new(w, h);
+	data.install(&mut world);
+	world.reseed(seed);
+	generator::generate(&mut world);
+	match mapfile::MapData::from_world(&world).save(path) {
+		Ok(()) => println!("exported map of seed {} to {}", seed, path.display()),
+		Err(e) => println!("could not export map: {}", e.desc)
+	}
+}
+
+fn run_headless(w: uint, h: uint, seed: u32, turns: uint, record_file: Option<String>, data: GameData, map: &Option<mapfile::MapData>) {
+	let mut world = world::World::with_input(w, h, box input::RandomInput::new(seed));
+	data.install(&mut world);
+	start_run(&mut world, seed, map);
+
+	let outcome = headless::run(&mut world, turns);
+	print_outcome(seed, &outcome);
+
+	if let Some(record_file) = record_file {
+		if let Err(e) = replay::Replay::from_world(&world).save(&Path::new(record_file)) {
+			println!("could not save replay: {}", e.desc);
+		}
+	}
+}
+
+fn print_outcome(seed: u32, outcome: &headless::Outcome) {
+	println!("seed: {} ticks: {} kills: {} shots: {} damage taken: {} alive: {}",
+		seed, outcome.ticks, outcome.kills, outcome.shots_fired, outcome.damage_taken, outcome.player_alive);
+}
+
+// the recorded run's world, on its map if it had one, whose player is driven by
+// the recorded keys
+fn start_replay(replay: &replay::Replay, data: GameData) -> world::World {
+	let mut world = world::World::with_input(replay.width, replay.height, box replay.input());
+	data.install(&mut world);
+	start_run(&mut world, replay.seed, &replay.map);
+	world
+}
+
+fn run_headless_replay(replay: &replay::Replay, turns: uint, data: GameData) {
+	let mut world = start_replay(replay, data);
+	let outcome = headless::run(&mut world, turns);
+	print_outcome(replay.seed, &outcome);
+}
+
+fn run_replay(replay: &replay::Replay, view_width: uint, view_height: uint, data: GameData) {
+	let mut renderer = renderer::Renderer::new(view_width, view_height, "kobold mayhem - replay");
+	let mut world = start_replay(replay, data);
+
+	while !Console::window_closed() && !world.is_game_over() && !headless::is_out_of_input(&world) {
+		let keys_before = world.input_log.len();
+		world.tick();
+		if world.input_log.len() > keys_before {
+			// slow down to a watchable pace whenever the player moves
+			renderer.draw_world(&world);
+			timer::sleep(Duration::milliseconds(50));
+		}
+	}
+
+	renderer.draw_world(&world);
+	if world.is_game_over() {
+		renderer.draw_game_over();
+	}
+	console::wait_for_any_key();
+}
@@ -0,0 +1,313 @@
+use util::Point;
+use world::{World, CellType};
+
+use serialize::json;
+
+use std::io::{File, IoResult};
+
+static MAP_MAGIC: &'static str = "rrl-map";
+static MAP_VERSION: uint = 1;
+
+// template id the player is placed as
+pub static PLAYER_TEMPLATE: &'static str = "player";
+
+#[deriving(Clone, Encodable, Decodable)]
+pub struct ActorPlacement {
+	pub template: String,
+	pub x: uint,
+	pub y: uint
+}
+
+// A level layout without any run state: cell glyph rows and which templates
+// stand where. Written as plain text, or as JSON when the file ends in .json.
+//
+//   rrl-map <version>
+//   size <width> <height>
+//   grid
+//   <height rows of cell glyphs>
+//   actors <count>
+//   <template id> <x> <y>
+//   end
+#[deriving(Clone, Encodable, Decodable)]
+pub struct MapData {
+	pub width: uint,
+	pub height: uint,
+	pub rows: Vec<String>,
+	pub actors: Vec<ActorPlacement>
+}
+
+impl MapData {
+	// the current level. Actors that weren't built from a template are left out.
+	pub fn from_world(world: &World) -> MapData {
+		let mut rows = Vec::with_capacity(world.height);
+		for y in range(0, world.height) {
+			let mut row = String::with_capacity(world.width);
+			for x in range(0, world.width) {
+				row.push(world.get_cell(x, y).get_glyph());
+			}
+			rows.push(row);
+		}
+
+		let mut actors = Vec::new();
+		for id in world.actors.ids().iter() {
+			let actor = world.actors.get(id).unwrap();
+			let template = if actor.is_player { Some(PLAYER_TEMPLATE.to_string()) } else { actor.template.clone() };
+			if let (Some(template), Some(position)) = (template, actor.position.as_ref()) {
+				actors.push(ActorPlacement {template: template, x: position.x, y: position.y});
+			}
+		}
+
+		MapData {width: world.width, height: world.height, rows: rows, actors: actors}
+	}
+
+	// Replaces world with a fresh run on this map. The world is left untouched if
+	// the map doesn't fit it.
+	pub fn apply(&self, world: &mut World) -> Result<(), String> {
+		let cell_types = try!(self.check(world));
+
+		world.cleanup();
+		for y in range(0, self.height) {
+			for x in range(0, self.width) {
+				world.grid[y][x].cell_type = cell_types[y][x].clone();
+			}
+		}
+
+		for placement in self.actors.iter() {
+			let position = Point::new(placement.x, placement.y);
+			if placement.template.as_slice() == PLAYER_TEMPLATE {
+				let player = world.player.clone();
+				world.set_actor_position(&player, &position);
+			} else if let Some(actor) = world.templates.build(placement.template.as_slice()) {
+				world.add_actor(actor, position);
+			}
+		}
+		world.start_map = Some(self.clone());
+		world.update_fov();
+		Ok(())
+	}
+
+	// the parsed cell types, if the map can be put into world
+	fn check(&self, world: &World) -> Result<Vec<Vec<CellType>>, String> {
+		if self.width != world.width || self.height != world.height || self.rows.len() != self.height {
+			return Err(format!("map is {}x{}, world is {}x{}", self.width, self.rows.len(), world.width, world.height));
+		}
+
+		let mut cell_types = Vec::with_capacity(self.height);
+		for (y, row) in self.rows.iter().enumerate() {
+			if row.as_slice().char_len() != self.width {
+				return Err(format!("row {} should be {} cells wide", y, self.width));
+			}
+			let mut cells = Vec::with_capacity(self.width);
+			for glyph in row.as_slice().chars() {
+				match CellType::from_glyph(glyph) {
+					Some(cell_type) => cells.push(cell_type),
+					None => return Err(format!("unknown cell glyph '{}' in row {}", glyph, y))
+				}
+			}
+			cell_types.push(cells);
+		}
+
+		let mut taken: Vec<Point> = Vec::new();
+		let mut players = 0u;
+		for placement in self.actors.iter() {
+			let position = Point::new(placement.x, placement.y);
+			if position.x >= self.width || position.y >= self.height {
+				return Err(format!("{} at {},{} is outside the map", placement.template, position.x, position.y));
+			}
+			if !cell_types[position.y][position.x].is_walkable() {
+				return Err(format!("{} at {},{} is on a cell nothing can stand on", placement.template, position.x, position.y));
+			}
+			if taken.contains(&position) {
+				return Err(format!("more than one actor at {},{}", position.x, position.y));
+			}
+			taken.push(position);
+
+			if placement.template.as_slice() == PLAYER_TEMPLATE {
+				players += 1;
+			} else if world.templates.get(placement.template.as_slice()).is_none() {
+				return Err(format!("unknown template '{}'", placement.template));
+			}
+		}
+		if players != 1 {
+			return Err(format!("map must place exactly one player, found {}", players));
+		}
+
+		Ok(cell_types)
+	}
+
+	pub fn to_text(&self) -> String {
+		let mut out = String::new();
+		out.push_str(format!("{} {}\n", MAP_MAGIC, MAP_VERSION).as_slice());
+		out.push_str(format!("size {} {}\n", self.width, self.height).as_slice());
+		out.push_str("grid\n");
+		for row in self.rows.iter() {
+			out.push_str(row.as_slice());
+			out.push('\n');
+		}
+		out.push_str(format!("actors {}\n", self.actors.len()).as_slice());
+		for placement in self.actors.iter() {
+			out.push_str(format!("{} {} {}\n", placement.template, placement.x, placement.y).as_slice());
+		}
+		out.push_str("end\n");
+		out
+	}
+
+	pub fn from_text(source: &str) -> Result<MapData, String> {
+		let lines: Vec<&str> = source.lines().collect();
+		let mut index = 0u;
+
+		let header = try!(words_of(&lines, &mut index, MAP_MAGIC));
+		if header.len() != 1 || header[0].parse::<uint>() != Some(MAP_VERSION) {
+			return Err(format!("line {}: unsupported map version (expected {})", index, MAP_VERSION));
+		}
+
+		let size = try!(words_of(&lines, &mut index, "size"));
+		let width = try!(number(&size, 0, index));
+		let height = try!(number(&size, 1, index));
+
+		try!(words_of(&lines, &mut index, "grid"));
+		let mut rows = Vec::with_capacity(height);
+		for _ in range(0, height) {
+			if index >= lines.len() {
+				return Err("map ends inside the grid".to_string());
+			}
+			rows.push(lines[index].to_string());
+			index += 1;
+		}
+
+		let count = try!(number(&try!(words_of(&lines, &mut index, "actors")), 0, index));
+		let mut actors = Vec::with_capacity(count);
+		for _ in range(0, count) {
+			if index >= lines.len() {
+				return Err("map ends inside the actors".to_string());
+			}
+			let words: Vec<&str> = lines[index].words().collect();
+			index += 1;
+			if words.len() != 3 {
+				return Err(format!("line {}: expected '<template> <x> <y>'", index));
+			}
+			actors.push(ActorPlacement {
+				template: words[0].to_string(),
+				x: try!(number(&words, 1, index)),
+				y: try!(number(&words, 2, index))
+			});
+		}
+
+		try!(words_of(&lines, &mut index, "end"));
+		Ok(MapData {width: width, height: height, rows: rows, actors: actors})
+	}
+
+	pub fn to_json(&self) -> String {
+		json::encode(self)
+	}
+
+	pub fn from_json(source: &str) -> Result<MapData, String> {
+		json::decode(source).map_err(|e| format!("bad map json: {}", e))
+	}
+
+	pub fn save(&self, path: &Path) -> IoResult<()> {
+		let contents = if is_json(path) { self.to_json() } else { self.to_text() };
+		let mut file = try!(File::create(path));
+		file.write_str(contents.as_slice())
+	}
+
+	pub fn load(path: &Path) -> Result<MapData, String> {
+		let contents = match File::open(path).read_to_string() {
+			Ok(contents) => contents,
+			Err(e) => return Err(format!("could not read {}: {}", path.display(), e.desc))
+		};
+		if is_json(path) {
+			MapData::from_json(contents.as_slice())
+		} else {
+			MapData::from_text(contents.as_slice())
+		}
+	}
+}
+
+fn is_json(path: &Path) -> bool {
+	path.extension_str() == Some("json")
+}
+
+// words after key on the next line, which has to start with key
+fn words_of<'a>(lines: &Vec<&'a str>, index: &mut uint, key: &str) -> Result<Vec<&'a str>, String> {
+	if *index >= lines.len() {
+		return Err(format!("map ends before '{}'", key));
+	}
+	let words: Vec<&str> = lines[*index].words().collect();
+	*index += 1;
+	if words.is_empty() || words[0] != key {
+		return Err(format!("line {}: expected '{}'", *index, key));
+	}
+	Ok(words.into_iter().skip(1).collect())
+}
+
+fn number(words: &Vec<&str>, position: uint, line: uint) -> Result<uint, String> {
+	if position >= words.len() {
+		return Err(format!("line {}: missing number", line));
+	}
+	match words[position].parse::<uint>() {
+		Some(value) => Ok(value),
+		None => Err(format!("line {}: bad number '{}'", line, words[position]))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{MapData, ActorPlacement};
+	use world::World;
+
+	fn small_map() -> MapData {
+		MapData {
+			width: 5,
+			height: 4,
+			rows: vec!["#####".to_string(), "#.~+#".to_string(), "#=.>#".to_string(), "#####".to_string()],
+			actors: vec![
+				ActorPlacement {template: "player".to_string(), x: 1, y: 1},
+				ActorPlacement {template: "kobold".to_string(), x: 2, y: 2}
+			]
+		}
+	}
+
+	fn assert_same(a: &MapData, b: &MapData) {
+		assert_eq!(a.width, b.width);
+		assert_eq!(a.height, b.height);
+		assert!(a.rows == b.rows);
+		assert_eq!(a.actors.len(), b.actors.len());
+		for (x, y) in a.actors.iter().zip(b.actors.iter()) {
+			assert_eq!(x.template, y.template);
+			assert_eq!(x.x, y.x);
+			assert_eq!(x.y, y.y);
+		}
+	}
+
+	#[test]
+	fn text_round_trip() {
+		let map = small_map();
+		let read = MapData::from_text(map.to_text().as_slice()).unwrap();
+		assert_same(&map, &read);
+	}
+
+	#[test]
+	fn json_round_trip() {
+		let map = small_map();
+		let read = MapData::from_json(map.to_json().as_slice()).unwrap();
+		assert_same(&map, &read);
+	}
+
+	#[test]
+	fn truncated_text_is_rejected() {
+		let text = small_map().to_text();
+		let lines: Vec<&str> = text.as_slice().lines().collect();
+		assert!(MapData::from_text(lines.slice_to(5).connect("\n").as_slice()).is_err());
+	}
+
+	#[test]
+	fn applied_map_comes_back_out() {
+		let map = small_map();
+		let mut world = World::new(5, 4);
+		map.apply(&mut world).unwrap();
+
+		assert!(world.start_map.is_some());
+		assert_same(&map, &MapData::from_world(&world));
+	}
+}
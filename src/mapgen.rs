@@ -0,0 +1,253 @@
+use util::{Point, GameRng};
+
+use std::rand::Rng;
+use std::num::SignedInt;
+
+pub static FLOOR: uint = 0;
+pub static WALL: uint = 1;
+
+// Lays out the walls and floors of a level as height rows of FLOOR or WALL, with
+// a solid wall border. The result may have unconnected pockets, see
+// generator::connect_regions.
+pub trait MapGenerator {
+	fn generate(&self, width: uint, height: uint, rng: &mut GameRng) -> Vec<Vec<uint>>;
+}
+
+// one of the generators below with its default parameters, so levels vary
+pub fn pick(rng: &mut GameRng) -> Box<MapGenerator + 'static> {
+	match rng.gen_range(0u, 3) {
+		0 => box CaveGenerator::new() as Box<MapGenerator>,
+		1 => box BspGenerator::new() as Box<MapGenerator>,
+		_ => box DrunkardGenerator::new() as Box<MapGenerator>
+	}
+}
+
+fn solid(width: uint, height: uint) -> Vec<Vec<uint>> {
+	Vec::from_elem(height, Vec::from_elem(width, WALL))
+}
+
+// L-shaped corridor, horizontal leg first
+pub fn carve_tunnel(grid: &mut Vec<Vec<uint>>, from: &Point, to: &Point) {
+	let mut x = from.x;
+	let mut y = from.y;
+	grid[y][x] = FLOOR;
+	while x != to.x {
+		if x < to.x { x += 1; } else { x -= 1; }
+		grid[y][x] = FLOOR;
+	}
+	while y != to.y {
+		if y < to.y { y += 1; } else { y -= 1; }
+		grid[y][x] = FLOOR;
+	}
+}
+
+// Cellular automata caves.
+// http://www.roguebasin.com/index.php?title=Cellular_Automata_Method_for_Generating_Random_Cave-Like_Levels#C_Code
+pub struct CaveGenerator {
+	// chance in percent that a cell starts out as wall
+	pub fill_prob: uint,
+	pub generations: uint,
+	// a cell becomes wall with at least this many walls within 1 step...
+	pub r1_cutoff: uint,
+	// ...or at most this many within 2 steps, which breaks up open spaces
+	pub r2_cutoff: uint
+}
+
+impl CaveGenerator {
+	pub fn new() -> CaveGenerator {
+		CaveGenerator {fill_prob: 40, generations: 5, r1_cutoff: 5, r2_cutoff: 2}
+	}
+}
+
+impl MapGenerator for CaveGenerator {
+	fn generate(&self, width: uint, height: uint, rng: &mut GameRng) -> Vec<Vec<uint>> {
+		let mut grid : Vec<Vec<uint>> = Vec::with_capacity(height);
+		let mut grid2 : Vec<Vec<uint>> = Vec::with_capacity(height);
+
+		// fill grid with random
+		for y in range (0, height) {
+			let mut rows:Vec<uint> = Vec::with_capacity(width);
+			let mut rows2:Vec<uint> = Vec::with_capacity(width);
+
+			for x in range (0, width) {
+				let mut cell_type = FLOOR;
+				if x == 0 || y == 0 || x == width - 1 || y == height -1 {
+					cell_type = WALL;
+				} else if rng.gen_range(0u, 100) < self.fill_prob {
+					cell_type = WALL;
+				}
+				rows.push(cell_type);
+				rows2.push(WALL);
+			}
+			grid.push(rows);
+			grid2.push(rows2);
+		}
+
+		for _ in range (0, self.generations) {
+			for y in range (1i, height as int - 1) {
+				for x in range (1i, width as int - 1) {
+
+					let mut adjacent_count_r1 = 0u;
+					let mut adjacent_count_r2 = 0u;
+
+					// the number of tiles within 1 step of p which are walls
+					for yy in range (-1i, 2) {
+						for xx in range (-1i, 2) {
+							let yyy = y + yy;
+							let xxx = x + xx;
+							if grid[yyy as uint][xxx as uint] == WALL {
+								adjacent_count_r1 += 1;
+							}
+						}
+					}
+					// p is in the middle of an open space
+					for yy in range (y - 2i, y + 3) {
+						for xx in range (x -2i, x + 3) {
+
+							// skip p itself
+							if (xx - x).abs() == 2 && (yy - y).abs() == 2 {
+								continue;
+							}
+
+							if xx >= 0 && xx < width as int && yy >= 0 && yy < height as int {
+								if grid[yy as uint][xx as uint] == WALL {
+									adjacent_count_r2 += 1;
+								}
+							}
+						}
+					}
+
+					if adjacent_count_r1 >= self.r1_cutoff || adjacent_count_r2 <= self.r2_cutoff {
+						grid2[y as uint][x as uint] = WALL;
+					} else {
+						grid2[y as uint][x as uint] = FLOOR;
+					}
+				}
+			}
+
+			for y in range (0, height) {
+				for x in range (0, width) {
+					grid[y][x] = grid2[y][x];
+				}
+			}
+		}
+
+		grid
+	}
+}
+
+struct Rect {
+	x: uint,
+	y: uint,
+	width: uint,
+	height: uint
+}
+
+// Rooms and corridors. The map is split in two again and again, each leaf gets a
+// room and the halves of every split are joined by a corridor.
+pub struct BspGenerator {
+	pub min_room_size: uint,
+	// how many times the map is split at most
+	pub max_splits: uint
+}
+
+impl BspGenerator {
+	pub fn new() -> BspGenerator {
+		BspGenerator {min_room_size: 4, max_splits: 5}
+	}
+
+	// carves the rooms of rect and returns the center of one of them
+	fn split(&self, rect: &Rect, splits_left: uint, grid: &mut Vec<Vec<uint>>, rng: &mut GameRng) -> Point {
+		// a part needs room for the smallest room and a wall on either side
+		let min_part = self.min_room_size + 2;
+		let can_split_x = rect.width >= min_part * 2;
+		let can_split_y = rect.height >= min_part * 2;
+
+		if splits_left == 0 || (!can_split_x && !can_split_y) {
+			return self.carve_room(rect, grid, rng);
+		}
+
+		let split_x = if can_split_x && can_split_y { rect.width >= rect.height } else { can_split_x };
+		let (first, second) = if split_x {
+			let at = rng.gen_range(min_part, rect.width - min_part + 1);
+			(Rect {x: rect.x, y: rect.y, width: at, height: rect.height},
+			 Rect {x: rect.x + at, y: rect.y, width: rect.width - at, height: rect.height})
+		} else {
+			let at = rng.gen_range(min_part, rect.height - min_part + 1);
+			(Rect {x: rect.x, y: rect.y, width: rect.width, height: at},
+			 Rect {x: rect.x, y: rect.y + at, width: rect.width, height: rect.height - at})
+		};
+
+		let a = self.split(&first, splits_left - 1, grid, rng);
+		let b = self.split(&second, splits_left - 1, grid, rng);
+		carve_tunnel(grid, &a, &b);
+		a
+	}
+
+	fn carve_room(&self, rect: &Rect, grid: &mut Vec<Vec<uint>>, rng: &mut GameRng) -> Point {
+		// keep a wall between the room and the edge of its part
+		let room_width = rng.gen_range(self.min_room_size, rect.width - 1);
+		let room_height = rng.gen_range(self.min_room_size, rect.height - 1);
+		let x = rect.x + 1 + rng.gen_range(0u, rect.width - room_width - 1);
+		let y = rect.y + 1 + rng.gen_range(0u, rect.height - room_height - 1);
+
+		for yy in range(y, y + room_height) {
+			for xx in range(x, x + room_width) {
+				grid[yy][xx] = FLOOR;
+			}
+		}
+		Point::new(x + room_width / 2, y + room_height / 2)
+	}
+}
+
+impl MapGenerator for BspGenerator {
+	fn generate(&self, width: uint, height: uint, rng: &mut GameRng) -> Vec<Vec<uint>> {
+		let mut grid = solid(width, height);
+		let map = Rect {x: 0, y: 0, width: width, height: height};
+		self.split(&map, self.max_splits, &mut grid, rng);
+		grid
+	}
+}
+
+// A random walk from the middle of the map that digs out floor until enough of
+// the map is open. Gives winding, uneven caves.
+pub struct DrunkardGenerator {
+	// percentage of the map to dig out
+	pub floor_percent: uint
+}
+
+impl DrunkardGenerator {
+	pub fn new() -> DrunkardGenerator {
+		DrunkardGenerator {floor_percent: 40}
+	}
+}
+
+impl MapGenerator for DrunkardGenerator {
+	fn generate(&self, width: uint, height: uint, rng: &mut GameRng) -> Vec<Vec<uint>> {
+		let mut grid = solid(width, height);
+		let target = (width - 2) * (height - 2) * self.floor_percent / 100;
+		// a walk that keeps getting stuck in dug out space still ends
+		let max_steps = width * height * 20;
+
+		let mut x = width / 2;
+		let mut y = height / 2;
+		grid[y][x] = FLOOR;
+		let mut dug = 1u;
+
+		let mut steps = 0u;
+		while dug < target && steps < max_steps {
+			match rng.gen_range(0u, 4) {
+				0 => if y > 1 { y -= 1 },
+				1 => if y < height - 2 { y += 1 },
+				2 => if x > 1 { x -= 1 },
+				_ => if x < width - 2 { x += 1 }
+			}
+			if grid[y][x] == WALL {
+				grid[y][x] = FLOOR;
+				dug += 1;
+			}
+			steps += 1;
+		}
+		grid
+	}
+}
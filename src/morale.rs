@@ -0,0 +1,70 @@
+use util::Point;
+use actor::Actor;
+use world::World;
+use template::Role;
+
+// how far away other monsters count as backup
+pub static ALLY_RADIUS: uint = 6;
+// a wounded monster stands its ground with at least this many allies around,
+// and a regrouping one heads back out once this many have gathered
+pub static PACK_SIZE: uint = 3;
+// close enough to a spawner to regroup there
+pub static REGROUP_RADIUS: uint = 3;
+// far enough from the player to regroup when there is no spawner to run to
+pub static SAFE_DISTANCE: uint = 15;
+
+// at half health or below
+pub fn is_wounded(actor: &Actor) -> bool {
+	actor.health.as_ref().map_or(false, |health| health.current * 2 <= health.max)
+}
+
+// A wounded monster without enough backup loses its nerve.
+pub fn should_flee(world: &World, position: &Point) -> bool {
+	let wounded = match world.actor_at(position) {
+		Some(id) => world.actors.get(&id).map_or(false, |actor| is_wounded(actor)),
+		None => false
+	};
+	wounded && allies_near(world, position, ALLY_RADIUS) < PACK_SIZE
+}
+
+// other monsters within radius of position
+pub fn allies_near(world: &World, position: &Point, radius: uint) -> uint {
+	let mut count = 0u;
+	for id in world.actors.ids().iter() {
+		let actor = world.actors.get(id).unwrap();
+		if !actor.is_alive() || !has_role(world, actor, Role::Monster) {
+			continue;
+		}
+		if let Some(ref p) = actor.position {
+			if *p != *position && p.distance_to(position) <= radius {
+				count += 1;
+			}
+		}
+	}
+	count
+}
+
+// the closest spawner, where fleeing monsters gather
+pub fn nearest_spawner(world: &World, position: &Point) -> Option<Point> {
+	let mut closest: Option<(uint, Point)> = None;
+	for id in world.actors.ids().iter() {
+		let actor = world.actors.get(id).unwrap();
+		if !has_role(world, actor, Role::Spawner) {
+			continue;
+		}
+		if let Some(ref p) = actor.position {
+			let distance = p.distance_to(position);
+			if closest.as_ref().map_or(true, |&(best, _)| distance < best) {
+				closest = Some((distance, p.clone()));
+			}
+		}
+	}
+	closest.map(|(_, p)| p)
+}
+
+fn has_role(world: &World, actor: &Actor, role: Role) -> bool {
+	match actor.template {
+		Some(ref id) => world.templates.get(id.as_slice()).map_or(false, |template| template.role == role),
+		None => false
+	}
+}
@@ -0,0 +1,371 @@
+use util::Point;
+use world::CellType;
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+// cost of one step onto plain floor, the heuristic assumes no step is cheaper
+// unless a search overrides some costs below it
+static STEP_COST: uint = 100;
+
+// What A* searches over. Kept apart from World so it can run on any grid.
+pub trait PathGrid {
+	fn size(&self) -> (uint, uint);
+	// cost of stepping onto p, None if it can't be entered at all
+	fn step_cost(&self, p: &Point) -> Option<uint>;
+	// what p is, for costs a search overrides
+	fn cell_type(&self, p: &Point) -> CellType;
+	// whether something that blocks movement stands on p
+	fn is_occupied(&self, p: &Point) -> bool;
+}
+
+pub struct PathOptions {
+	pub diagonal: bool,
+	// treat cells with actors on them as walls, except the goal
+	pub avoid_actors: bool,
+	// how many cells may be expanded before giving up
+	pub max_nodes: uint,
+	// step costs that replace the grid's own for some cell types, None keeps
+	// the path off them, e.g. to stay out of water
+	pub costs: Vec<(CellType, Option<uint>)>
+}
+
+impl PathOptions {
+	pub fn new() -> PathOptions {
+		PathOptions {diagonal: false, avoid_actors: false, max_nodes: 4000, costs: Vec::new()}
+	}
+
+	pub fn with_cost(mut self, cell_type: CellType, cost: Option<uint>) -> PathOptions {
+		self.costs.push((cell_type, cost));
+		self
+	}
+
+	// the cheapest step a search with these options can take, so the estimate
+	// never overshoots
+	fn cheapest_step(&self) -> uint {
+		self.costs.iter().fold(STEP_COST, |cheapest, &(_, cost)| {
+			match cost {
+				Some(cost) if cost < cheapest => cost,
+				_ => cheapest
+			}
+		})
+	}
+}
+
+// A cell queued for a search, here and in dijkstra. The one with the lowest
+// priority is expanded next, cost is what it took to get there.
+#[deriving(PartialEq, Eq)]
+pub struct Node<T> {
+	pub priority: T,
+	pub cost: T,
+	pub x: uint,
+	pub y: uint
+}
+
+// BinaryHeap pops the largest, so the lowest priority has to compare largest
+impl<T: Ord> Ord for Node<T> {
+	fn cmp(&self, other: &Node<T>) -> Ordering {
+		other.priority.cmp(&self.priority)
+	}
+}
+
+impl<T: Ord> PartialOrd for Node<T> {
+	fn partial_cmp(&self, other: &Node<T>) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+// The cheapest path from from to to, without from and ending on to. None if
+// there is no path or the search ran out of budget.
+pub fn find_path<G: PathGrid>(grid: &G, from: &Point, to: &Point, options: &PathOptions) -> Option<Vec<Point>> {
+	let (width, height) = grid.size();
+	if from.x >= width || from.y >= height || to.x >= width || to.y >= height {
+		return None;
+	}
+	if *from == *to {
+		return Some(Vec::new());
+	}
+
+	let mut best: Vec<Vec<Option<uint>>> = Vec::from_elem(height, Vec::from_elem(width, None));
+	let mut came_from: Vec<Vec<Option<Point>>> = Vec::from_elem(height, Vec::from_elem(width, None));
+	let mut open = BinaryHeap::new();
+	let cheapest = options.cheapest_step();
+
+	best[from.y][from.x] = Some(0);
+	open.push(Node {priority: estimate(from, to, options.diagonal, cheapest), cost: 0u, x: from.x, y: from.y});
+
+	let mut expanded = 0u;
+	while let Some(node) = open.pop() {
+		let current = Point::new(node.x, node.y);
+		if current == *to {
+			return Some(walk_back(&came_from, from, to));
+		}
+		// a cheaper way here was found after this node was queued
+		if best[node.y][node.x].map_or(false, |cost| cost < node.cost) {
+			continue;
+		}
+
+		expanded += 1;
+		if expanded > options.max_nodes {
+			return None;
+		}
+
+		for next in neighbours(&current, width, height, options.diagonal).into_iter() {
+			if options.avoid_actors && next != *to && grid.is_occupied(&next) {
+				continue;
+			}
+			let step = match step_cost(grid, &next, options) {
+				Some(step) => step,
+				None => continue
+			};
+			let cost = node.cost + step;
+			if best[next.y][next.x].map_or(true, |known| cost < known) {
+				best[next.y][next.x] = Some(cost);
+				came_from[next.y][next.x] = Some(current.clone());
+				open.push(Node {priority: cost + estimate(&next, to, options.diagonal, cheapest), cost: cost, x: next.x, y: next.y});
+			}
+		}
+	}
+	None
+}
+
+// The grid's cost of stepping onto p, or the one options have for its cell type.
+// Cells the grid won't let anyone onto stay closed.
+fn step_cost<G: PathGrid>(grid: &G, p: &Point, options: &PathOptions) -> Option<uint> {
+	let step = match grid.step_cost(p) {
+		Some(step) => step,
+		None => return None
+	};
+	if options.costs.is_empty() {
+		return Some(step);
+	}
+	let cell_type = grid.cell_type(p);
+	match options.costs.iter().find(|&&(ref overridden, _)| *overridden == cell_type) {
+		Some(&(_, cost)) => cost,
+		None => Some(step)
+	}
+}
+
+// fewest steps from a to b, times the cheapest step
+fn estimate(a: &Point, b: &Point, diagonal: bool, cheapest: uint) -> uint {
+	let dx = if a.x > b.x { a.x - b.x } else { b.x - a.x };
+	let dy = if a.y > b.y { a.y - b.y } else { b.y - a.y };
+	let steps = if diagonal { if dx > dy { dx } else { dy } } else { dx + dy };
+	steps * cheapest
+}
+
+// the cells next to p inside a width by height grid, with or without the
+// diagonal ones
+pub fn neighbours(p: &Point, width: uint, height: uint, diagonal: bool) -> Vec<Point> {
+	let mut result = Vec::with_capacity(8);
+	for dy in range(-1i, 2) {
+		for dx in range(-1i, 2) {
+			if (dx == 0 && dy == 0) || (!diagonal && dx != 0 && dy != 0) {
+				continue;
+			}
+			let x = p.x as int + dx;
+			let y = p.y as int + dy;
+			if x >= 0 && y >= 0 && x < width as int && y < height as int {
+				result.push(Point::new(x as uint, y as uint));
+			}
+		}
+	}
+	result
+}
+
+fn walk_back(came_from: &Vec<Vec<Option<Point>>>, from: &Point, to: &Point) -> Vec<Point> {
+	let mut path = vec![to.clone()];
+	let mut current = to.clone();
+	loop {
+		let previous = match came_from[current.y][current.x] {
+			Some(ref previous) if *previous != *from => previous.clone(),
+			_ => break
+		};
+		path.push(previous.clone());
+		current = previous;
+	}
+	path.reverse();
+	path
+}
+
+#[cfg(test)]
+pub mod test {
+	use super::{PathGrid, PathOptions, find_path};
+	use util::Point;
+	use world::CellType;
+
+	// a grid drawn with map glyphs, k is floor with an actor on it. The
+	// dijkstra tests use it too.
+	pub struct TestGrid {
+		cells: Vec<Vec<CellType>>,
+		actors: Vec<Point>
+	}
+
+	impl TestGrid {
+		pub fn new(rows: &[&str]) -> TestGrid {
+			let mut cells = Vec::new();
+			let mut actors = Vec::new();
+			for (y, row) in rows.iter().enumerate() {
+				let mut cell_row = Vec::new();
+				for (x, glyph) in row.chars().enumerate() {
+					if glyph == 'k' {
+						actors.push(Point::new(x, y));
+						cell_row.push(CellType::Floor);
+					} else {
+						cell_row.push(CellType::from_glyph(glyph).unwrap());
+					}
+				}
+				cells.push(cell_row);
+			}
+			TestGrid {cells: cells, actors: actors}
+		}
+	}
+
+	impl PathGrid for TestGrid {
+		fn size(&self) -> (uint, uint) {
+			(self.cells[0].len(), self.cells.len())
+		}
+
+		fn step_cost(&self, p: &Point) -> Option<uint> {
+			let cell_type = &self.cells[p.y][p.x];
+			if cell_type.is_passable() { Some(cell_type.path_cost() as uint) } else { None }
+		}
+
+		fn cell_type(&self, p: &Point) -> CellType {
+			self.cells[p.y][p.x].clone()
+		}
+
+		fn is_occupied(&self, p: &Point) -> bool {
+			self.actors.iter().any(|actor| *actor == *p)
+		}
+	}
+
+	fn points(coordinates: &[(uint, uint)]) -> Vec<Point> {
+		coordinates.iter().map(|&(x, y)| Point::new(x, y)).collect()
+	}
+
+	#[test]
+	fn straight_path() {
+		let grid = TestGrid::new(&["....."]);
+		let path = find_path(&grid, &Point::new(0, 0), &Point::new(4, 0), &PathOptions::new());
+		assert!(path == Some(points(&[(1, 0), (2, 0), (3, 0), (4, 0)])));
+	}
+
+	#[test]
+	fn already_there() {
+		let grid = TestGrid::new(&["..."]);
+		let path = find_path(&grid, &Point::new(1, 0), &Point::new(1, 0), &PathOptions::new());
+		assert!(path == Some(Vec::new()));
+	}
+
+	#[test]
+	fn around_walls() {
+		let grid = TestGrid::new(&[
+			"...",
+			".#.",
+			"..."]);
+		let path = find_path(&grid, &Point::new(1, 0), &Point::new(1, 2), &PathOptions::new()).unwrap();
+		assert_eq!(path.len(), 4);
+		assert!(path.iter().all(|p| *p != Point::new(1, 1)));
+		assert!(path[path.len() - 1] == Point::new(1, 2));
+	}
+
+	#[test]
+	fn walled_in_goal() {
+		let grid = TestGrid::new(&[
+			".###",
+			".#.#",
+			".###"]);
+		assert!(find_path(&grid, &Point::new(0, 0), &Point::new(2, 1), &PathOptions::new()).is_none());
+	}
+
+	#[test]
+	fn diagonal_steps() {
+		let grid = TestGrid::new(&[
+			"...",
+			"...",
+			"..."]);
+		let mut options = PathOptions::new();
+		let straight = find_path(&grid, &Point::new(0, 0), &Point::new(2, 2), &options).unwrap();
+		assert_eq!(straight.len(), 4);
+
+		options.diagonal = true;
+		let diagonal = find_path(&grid, &Point::new(0, 0), &Point::new(2, 2), &options).unwrap();
+		assert!(diagonal == points(&[(1, 1), (2, 2)]));
+	}
+
+	#[test]
+	fn avoiding_actors() {
+		let grid = TestGrid::new(&[".k.."]);
+		let mut options = PathOptions::new();
+		assert!(find_path(&grid, &Point::new(0, 0), &Point::new(3, 0), &options).is_some());
+
+		options.avoid_actors = true;
+		assert!(find_path(&grid, &Point::new(0, 0), &Point::new(3, 0), &options).is_none());
+	}
+
+	#[test]
+	fn occupied_goal_stays_enterable() {
+		let grid = TestGrid::new(&["..k"]);
+		let mut options = PathOptions::new();
+		options.avoid_actors = true;
+		let path = find_path(&grid, &Point::new(0, 0), &Point::new(2, 0), &options);
+		assert!(path == Some(points(&[(1, 0), (2, 0)])));
+	}
+
+	#[test]
+	fn out_of_budget() {
+		let grid = TestGrid::new(&[
+			"..........",
+			"..........",
+			".........."]);
+		let mut options = PathOptions::new();
+		options.max_nodes = 3;
+		assert!(find_path(&grid, &Point::new(0, 0), &Point::new(9, 2), &options).is_none());
+
+		options.max_nodes = 100;
+		assert!(find_path(&grid, &Point::new(0, 0), &Point::new(9, 2), &options).is_some());
+	}
+
+	#[test]
+	fn overridden_costs() {
+		let grid = TestGrid::new(&[
+			".~.",
+			"..."]);
+		let from = Point::new(0, 0);
+		let to = Point::new(2, 0);
+
+		// wading across is still cheaper than going round
+		let through = find_path(&grid, &from, &to, &PathOptions::new()).unwrap();
+		assert!(through == points(&[(1, 0), (2, 0)]));
+
+		let dry = PathOptions::new().with_cost(CellType::Water, None);
+		let around = find_path(&grid, &from, &to, &dry).unwrap();
+		assert!(around == points(&[(0, 1), (1, 1), (2, 1), (2, 0)]));
+
+		// overrides don't open up walls
+		let walled = TestGrid::new(&[".#."]);
+		let through_walls = PathOptions::new().with_cost(CellType::Wall, Some(100));
+		assert!(find_path(&walled, &from, &to, &through_walls).is_none());
+	}
+
+	#[test]
+	fn costs_below_a_step() {
+		let grid = TestGrid::new(&[
+			".....",
+			"~~~~~"]);
+		// the long way through cheap water beats the floor
+		let cheap_water = PathOptions::new().with_cost(CellType::Water, Some(10));
+		let path = find_path(&grid, &Point::new(0, 0), &Point::new(4, 0), &cheap_water).unwrap();
+		assert!(path == points(&[(0, 1), (1, 1), (2, 1), (3, 1), (4, 1), (4, 0)]));
+	}
+
+	#[test]
+	fn around_lava() {
+		let grid = TestGrid::new(&[
+			".=.",
+			"..."]);
+		let path = find_path(&grid, &Point::new(0, 0), &Point::new(2, 0), &PathOptions::new()).unwrap();
+		assert!(path.iter().all(|p| *p != Point::new(1, 0)));
+	}
+}
@@ -0,0 +1,72 @@
+use util::Point;
+use world::World;
+use event::{GameEvent, EventListener};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// how far a sound carries, in steps
+static WALK_NOISE: uint = 2;
+static GUNFIRE_NOISE: uint = 15;
+
+// Something monsters can hear. Noises last until the player acts again, so
+// every monster gets a turn to notice them.
+#[deriving(Clone)]
+pub struct Noise {
+	pub position: Point,
+	pub radius: uint
+}
+
+// the sound an event makes, if any. Only what the player does is worth
+// listening for, monsters don't come running to each other's shots.
+pub fn noise_of(event: &GameEvent) -> Option<Noise> {
+	match *event {
+		GameEvent::ProjectileFired(ref shooter, _) if shooter.is_player => {
+			shooter.position.as_ref().map(|position| Noise {position: position.clone(), radius: GUNFIRE_NOISE})
+		},
+		GameEvent::ActorMoved(ref actor, _) if actor.is_player => {
+			actor.position.as_ref().map(|position| Noise {position: position.clone(), radius: WALK_NOISE})
+		},
+		_ => None
+	}
+}
+
+// Collects the noises events make for the world, see World::noises.
+pub struct NoiseListener {
+	pub noises: Rc<RefCell<Vec<Noise>>>
+}
+
+impl NoiseListener {
+	pub fn new() -> NoiseListener {
+		NoiseListener {noises: Rc::new(RefCell::new(Vec::new()))}
+	}
+}
+
+impl EventListener for NoiseListener {
+	fn on_event(&mut self, event: &GameEvent) {
+		if let Some(noise) = noise_of(event) {
+			self.noises.borrow_mut().push(noise);
+		}
+	}
+}
+
+// Sight goes both ways: a monster sees the player when the player could see
+// it, so walls and closed doors hide them from each other.
+pub fn can_see_player(world: &World, position: &Point) -> bool {
+	!world.is_game_over() && world.is_visible(position)
+}
+
+// where the closest noise within earshot of position came from
+pub fn hear(world: &World, position: &Point) -> Option<Point> {
+	let mut closest: Option<(uint, Point)> = None;
+	for noise in world.noises.borrow().iter() {
+		let distance = position.distance_to(&noise.position);
+		if distance > noise.radius {
+			continue;
+		}
+		if closest.as_ref().map_or(true, |&(best, _)| distance < best) {
+			closest = Some((distance, noise.position.clone()));
+		}
+	}
+	closest.map(|(_, position)| position)
+}
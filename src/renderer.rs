@@ -1,9 +1,23 @@
 extern crate tcod;
 use tcod::{Console, BackgroundFlag, TextAlignment};
 
-use actor::Actor;
-use world::World;
-use util;
+use rrl::actor::Actor;
+use rrl::camera::Camera;
+use rrl::world::World;
+use rrl::template::TemplateRegistry;
+use rrl::system;
+use rrl::util;
+
+// the simulation's colours know nothing about tcod
+trait ToTcodColor {
+	fn to_tcod_color(&self) -> tcod::Color;
+}
+
+impl ToTcodColor for util::Color {
+	fn to_tcod_color(&self) -> tcod::Color {
+		tcod::Color::new(self.r, self.g, self.b)
+	}
+}
 
 pub struct Panel {
 	x: uint,
@@ -32,11 +46,13 @@ pub struct Renderer {
 	height: uint,
     con: Console,
     top_panel: Panel,
-    bottom_panel: Panel
+    bottom_panel: Panel,
+    camera: Camera
 }
 
 impl Renderer {
 
+	// width and height are the size of the map view, the world can be larger
 	pub fn new(width: uint, height: uint, title: &str) -> Renderer {
 		let panel_height = 3;
 		let top_panel = Panel::new(0, 0, width, panel_height, util::Color::panel_green(), util::Color::black());
@@ -48,11 +64,12 @@ impl Renderer {
 			height: height,
 			con: Console::init_root(width as int, window_height as int, title, false),
 			top_panel: top_panel, 
-			bottom_panel: bottom_panel
+			bottom_panel: bottom_panel,
+			camera: Camera::new(width, height)
 		}
 	}
 
-	pub fn draw_title(&mut self) {
+	pub fn draw_title(&mut self, can_continue: bool, templates: &TemplateRegistry) {
 		self.con.clear();
 
 		let mut y = 5;
@@ -77,16 +94,22 @@ impl Renderer {
 		self.con.print_ex(x, y, BackgroundFlag::None, alignment, 	"Arrow keys to move/fire");
 		self.con.print_ex(x, y + 1, BackgroundFlag::None, alignment, 	"Shift - toggle walk/aim");
 		self.con.print_ex(x, y + 2, BackgroundFlag::None, alignment, 	"w - wait turn");
+		self.con.print_ex(x, y + 3, BackgroundFlag::None, alignment, 	"> < - take the stairs down/up");
+		self.con.print_ex(x, y + 4, BackgroundFlag::None, alignment, 	"Esc - save and quit to title");
 
-		y += 5;
+		y += 6;
 
 		self.draw_title_actor_description(30, y, Actor::player());
-		self.draw_title_actor_description(30, y + 1, Actor::kobold());
-		self.draw_title_actor_description(30, y + 2, Actor::kobold_generator());
-		self.draw_title_actor_description(30, y + 3, Actor::ammo_crate());
+		for template in templates.iter() {
+			y += 1;
+			self.draw_title_actor_description(30, y, template.build());
+		}
 
-		y += 6;
+		y += 3;
 		self.con.print_ex(x, y, BackgroundFlag::None, alignment, 	"[ Press any key to start ]");
+		if can_continue {
+			self.con.print_ex(x, y + 1, BackgroundFlag::None, alignment, 	"[ c - continue saved game ]");
+		}
 
 		self.flush();
 	}
@@ -101,7 +124,9 @@ impl Renderer {
 	}
 
 	fn draw_title_actor_description(&mut self, x: int, y: int, actor: Actor) {
-		self.con.put_char_ex(x, y, actor.glyph, actor.color.to_tcod_color(), util::Color::black().to_tcod_color());
+		if let Some(ref renderable) = actor.renderable {
+			self.con.put_char_ex(x, y, renderable.glyph, renderable.color.to_tcod_color(), util::Color::black().to_tcod_color());
+		}
 		self.con.print_ex(x + 2, y , BackgroundFlag::None, TextAlignment::Left, actor.name.as_slice());
 
 	}
@@ -110,19 +135,37 @@ impl Renderer {
 		self.draw_top_panel(world);
 		self.draw_bottom_panel(world);
 
+		if let Some(ref position) = world.player().position {
+			self.camera.follow(position, world.width, world.height);
+		}
 		let y_offset = self.top_panel.height;
 
-		for y in range (0, world.height) {
-			for x in range(0, world.width) {
-				let cell = world.get_cell(x, y);
+		for y in range (0, self.height) {
+			for x in range(0, self.width) {
 				let dest_x = x as int;
 				let dest_y = (y + y_offset) as int;
-				self.con.put_char(dest_x, dest_y, cell.get_glyph(), BackgroundFlag::Set);		
+				let position = self.camera.to_world(x, y);
+				if position.x >= world.width || position.y >= world.height {
+					self.con.put_char_ex(dest_x, dest_y, ' ', util::Color::black().to_tcod_color(), util::Color::black().to_tcod_color());
+					continue;
+				}
+				let cell = world.get_cell(position.x, position.y);
+				// remembered cells are dimmed, never seen ones stay blank
+				let (glyph, color) = if cell.visible {
+					(cell.get_glyph(), cell.cell_type.color())
+				} else if cell.explored {
+					(cell.get_glyph(), util::Color::dark_grey())
+				} else {
+					(' ', util::Color::black())
+				};
+				self.con.put_char_ex(dest_x, dest_y, glyph, color.to_tcod_color(), util::Color::black().to_tcod_color());
 			}
 		}
 		
-		for actor_ref in world.actors.iter() {
-			self.draw_actor(actor_ref.borrow().deref());
+		for sprite in system::sprites(world).iter() {
+			if world.is_visible(&sprite.position) {
+				self.draw_sprite(sprite);
+			}
 		}
 
 		self.flush();
@@ -133,7 +176,7 @@ impl Renderer {
 		self.top_panel.clear();
 
 		let mut y = 0;
-		for message in world.messages.iter() {
+		for message in world.messages.borrow().iter() {
 			self.top_panel.con.print_ex(1, y, BackgroundFlag::None, TextAlignment::Left, message.as_slice());
 			y += 1;
 		}
@@ -152,7 +195,7 @@ impl Renderer {
 	fn draw_bottom_panel(&mut self, world: &World) {
 		self.bottom_panel.clear();
 
-		let player = world.player.borrow();
+		let player = world.player();
 		let player_state = &world.player_state;
 
 		self.bottom_panel.con.print_ex(1, 1, BackgroundFlag::None, TextAlignment::Left, "Health:");
@@ -165,9 +208,12 @@ impl Renderer {
 			self.bottom_panel.con.print_ex(40, 1, BackgroundFlag::None, TextAlignment::Left, "[Walking]");
 		}
 
-		self.bottom_panel.con.print_ex(9, 1, BackgroundFlag::None, TextAlignment::Left, player.health.to_string().as_slice());
-		self.bottom_panel.con.print_ex(22, 1, BackgroundFlag::None, TextAlignment::Left, player_state.ammo.to_string().as_slice());
-		self.bottom_panel.con.print_ex(37, 1, BackgroundFlag::None, TextAlignment::Left, player_state.kills.to_string().as_slice());
+		let health = player.health.as_ref().map_or(0, |health| health.current);
+		self.bottom_panel.con.print_ex(9, 1, BackgroundFlag::None, TextAlignment::Left, health.to_string().as_slice());
+		self.bottom_panel.con.print_ex(22, 1, BackgroundFlag::None, TextAlignment::Left, world.player_ammo().to_string().as_slice());
+		self.bottom_panel.con.print_ex(37, 1, BackgroundFlag::None, TextAlignment::Left, player_state.kills.get().to_string().as_slice());
+		self.bottom_panel.con.print_ex(52, 1, BackgroundFlag::None, TextAlignment::Left, format!("Depth: {}", world.depth).as_slice());
+		self.bottom_panel.con.print_ex(63, 1, BackgroundFlag::None, TextAlignment::Left, format!("Seed: {}", world.seed).as_slice());
 
 		Console::blit(&self.bottom_panel.con, // source console
 						self.bottom_panel.x as int, 
@@ -175,15 +221,18 @@ impl Renderer {
 						self.bottom_panel.width as int, 
 						self.bottom_panel.height as int, // source 
 						&mut self.con, // dest console 
-						0, 50 + self.top_panel.height as int, 
+						0, (self.height + self.top_panel.height) as int, 
 						1f32, 
 						1f32);
 	}
 
-	fn draw_actor(&mut self, actor: &Actor) {
-		let y_offset = self.top_panel.height;
-		let dest_y = (actor.get_position().y + y_offset) as int;
-		self.con.put_char_ex(actor.get_position().x as int, dest_y, actor.glyph, actor.color.to_tcod_color(), util::Color::black().to_tcod_color());		
+	fn draw_sprite(&mut self, sprite: &system::Sprite) {
+		let (x, y) = match self.camera.to_screen(&sprite.position) {
+			Some(screen) => screen,
+			None => return
+		};
+		let dest_y = (y + self.top_panel.height) as int;
+		self.con.put_char_ex(x as int, dest_y, sprite.renderable.glyph, sprite.renderable.color.to_tcod_color(), util::Color::black().to_tcod_color());		
 	}
 	
 	pub fn flush(&self) {
@@ -0,0 +1,166 @@
+use input::{KeyCode, ScriptedInput};
+use world::World;
+use mapfile::MapData;
+
+use std::io::{File, IoResult};
+use std::str::FromStr;
+
+static REPLAY_VERSION: uint = 2;
+static REPLAY_MAGIC: &'static str = "rrl-replay";
+
+pub static REPLAY_FILE: &'static str = "rrl.replay";
+
+// A run is fully determined by its seed, map size, the hand made map it started
+// on if any, and the keys the player pressed.
+pub struct Replay {
+	pub seed: u32,
+	pub width: uint,
+	pub height: uint,
+	pub keys: Vec<KeyCode>,
+	pub map: Option<MapData>
+}
+
+impl Replay {
+	pub fn from_world(world: &World) -> Replay {
+		Replay {
+			seed: world.seed,
+			width: world.width,
+			height: world.height,
+			keys: world.input_log.clone(),
+			map: world.start_map.clone()
+		}
+	}
+
+	pub fn input(&self) -> ScriptedInput {
+		ScriptedInput::new(self.keys.clone())
+	}
+
+	// rrl-replay <version>
+	// seed <seed>
+	// size <width> <height>
+	// keys <key tokens...>
+	// map <0|1>
+	// <the map the run started on in the map file format, see mapfile, if 1>
+	pub fn to_text(&self) -> String {
+		let mut keys = String::with_capacity(self.keys.len() * 2);
+		for key in self.keys.iter() {
+			keys.push(' ');
+			keys.push_str(key.token());
+		}
+
+		let mut out = String::new();
+		out.push_str(format!("{} {}\n", REPLAY_MAGIC, REPLAY_VERSION).as_slice());
+		out.push_str(format!("seed {}\n", self.seed).as_slice());
+		out.push_str(format!("size {} {}\n", self.width, self.height).as_slice());
+		out.push_str(format!("keys{}\n", keys).as_slice());
+		match self.map {
+			Some(ref map) => {
+				out.push_str("map 1\n");
+				out.push_str(map.to_text().as_slice());
+			},
+			None => out.push_str("map 0\n")
+		}
+		out
+	}
+
+	pub fn from_text(source: &str) -> Result<Replay, String> {
+		let lines: Vec<&str> = source.lines().collect();
+		if lines.len() < 5 {
+			return Err("replay file is truncated".to_string());
+		}
+
+		let header = try!(tokens(lines[0], REPLAY_MAGIC));
+		if header.len() != 1 || header[0].parse::<uint>() != Some(REPLAY_VERSION) {
+			return Err(format!("unsupported replay version (expected {})", REPLAY_VERSION));
+		}
+
+		let seed = try!(number::<u32>(try!(tokens(lines[1], "seed")).as_slice(), 0));
+		let size = try!(tokens(lines[2], "size"));
+		let width = try!(number::<uint>(size.as_slice(), 0));
+		let height = try!(number::<uint>(size.as_slice(), 1));
+
+		let mut keys = Vec::new();
+		for token in try!(tokens(lines[3], "keys")).iter() {
+			match KeyCode::from_token(*token) {
+				Some(key) => keys.push(key),
+				None => return Err(format!("unknown key '{}' in replay", token))
+			}
+		}
+
+		let has_map = try!(number::<uint>(try!(tokens(lines[4], "map")).as_slice(), 0));
+		let map = if has_map == 1 {
+			Some(try!(MapData::from_text(lines.slice_from(5).connect("\n").as_slice())))
+		} else {
+			None
+		};
+
+		Ok(Replay {seed: seed, width: width, height: height, keys: keys, map: map})
+	}
+
+	pub fn save(&self, path: &Path) -> IoResult<()> {
+		let mut file = try!(File::create(path));
+		file.write_str(self.to_text().as_slice())
+	}
+
+	pub fn load(path: &Path) -> Result<Replay, String> {
+		let contents = match File::open(path).read_to_string() {
+			Ok(contents) => contents,
+			Err(e) => return Err(format!("could not read {}: {}", path.display(), e.desc))
+		};
+		Replay::from_text(contents.as_slice())
+	}
+}
+
+fn tokens<'a>(line: &'a str, key: &str) -> Result<Vec<&'a str>, String> {
+	let mut words: Vec<&'a str> = line.words().collect();
+	if words.is_empty() || words[0] != key {
+		return Err(format!("expected '{}' in replay", key));
+	}
+	words.remove(0);
+	Ok(words)
+}
+
+fn number<T: FromStr>(tokens: &[&str], index: uint) -> Result<T, String> {
+	if index >= tokens.len() {
+		return Err("missing value in replay".to_string());
+	}
+	match tokens[index].parse::<T>() {
+		Some(value) => Ok(value),
+		None => Err(format!("bad number '{}' in replay", tokens[index]))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Replay;
+	use input::KeyCode;
+	use mapfile::{MapData, ActorPlacement};
+
+	fn replay(map: Option<MapData>) -> Replay {
+		Replay {seed: 1234, width: 3, height: 3, keys: vec![KeyCode::Up, KeyCode::Wait, KeyCode::Descend], map: map}
+	}
+
+	#[test]
+	fn round_trip() {
+		let read = Replay::from_text(replay(None).to_text().as_slice()).unwrap();
+		assert_eq!(read.seed, 1234);
+		assert_eq!((read.width, read.height), (3, 3));
+		assert!(read.keys == vec![KeyCode::Up, KeyCode::Wait, KeyCode::Descend]);
+		assert!(read.map.is_none());
+	}
+
+	#[test]
+	fn round_trip_with_map() {
+		let map = MapData {
+			width: 3,
+			height: 3,
+			rows: vec!["###".to_string(), "#.#".to_string(), "###".to_string()],
+			actors: vec![ActorPlacement {template: "player".to_string(), x: 1, y: 1}]
+		};
+		let read = Replay::from_text(replay(Some(map.clone())).to_text().as_slice()).unwrap();
+		assert!(read.keys.len() == 3);
+		let read_map = read.map.unwrap();
+		assert!(read_map.rows == map.rows);
+		assert_eq!(read_map.actors.len(), 1);
+	}
+}
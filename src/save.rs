@@ -0,0 +1,541 @@
+use util::{Point, Color, GameRng};
+use actor::{Actor, Renderable, Health, Ai, Pickup, PickupKind, Inventory, Blocker, load_brain};
+use world::{World, CellType};
+use input::KeyCode;
+use dungeon;
+use dungeon::Level;
+use mapfile::MapData;
+
+use std::io::{File, IoResult};
+use std::io::fs;
+use std::str::FromStr;
+
+// bump whenever the layout below changes, old saves are rejected
+static SAVE_VERSION: uint = 10;
+static SAVE_MAGIC: &'static str = "rrl-save";
+
+pub static SAVE_FILE: &'static str = "rrl.sav";
+
+// Line based text format:
+//
+//   rrl-save <version>
+//   seed <seed>
+//   rng <x> <y> <z> <w>
+//   size <width> <height>
+//   player <kills> <is_aiming>
+//   depth <depth>
+//   input_log <key tokens...>
+//   start_map <0|1>
+//   <the map the run started on in the map file format, see mapfile, if 1>
+//   grid
+//   <height rows of cell glyphs>
+//   explored
+//   <height rows of 1 for explored cells, 0 otherwise>
+//   messages <count>
+//   <one message per line>
+//   actors <count>
+//   actor <name>
+//   <one line per component the actor has, see write_actor>
+//   end_actor
+//   tactics <0|1> <player x> <player y, if 1>
+//   claims <count>
+//   claim <actor> <slot x> <slot y>
+//   levels <count>
+//   level <depth>
+//   <grid, explored and actors of a level the player left, as above>
+//   end_level
+//   end
+//
+// A claim names its actor by its place in the actors list, ids are handed out
+// anew on load.
+pub fn save(world: &World, path: &Path) -> IoResult<()> {
+	let mut out = String::new();
+
+	line(&mut out, format!("{} {}", SAVE_MAGIC, SAVE_VERSION));
+	line(&mut out, format!("seed {}", world.seed));
+	let rng_state = world.rng.state();
+	line(&mut out, format!("rng {} {} {} {}", rng_state[0], rng_state[1], rng_state[2], rng_state[3]));
+	line(&mut out, format!("size {} {}", world.width, world.height));
+
+	let player_state = &world.player_state;
+	line(&mut out, format!("player {} {}", player_state.kills.get(), bool_token(player_state.is_aiming)));
+	line(&mut out, format!("depth {}", world.depth));
+
+	// keeping the key log lets a resumed run still be replayed from its seed
+	let mut input_log = "input_log".to_string();
+	for key in world.input_log.iter() {
+		input_log.push(' ');
+		input_log.push_str(key.token());
+	}
+	line(&mut out, input_log);
+
+	// replays of a resumed run need the map it started on
+	match world.start_map {
+		Some(ref map) => {
+			line(&mut out, "start_map 1".to_string());
+			out.push_str(map.to_text().as_slice());
+		},
+		None => line(&mut out, "start_map 0".to_string())
+	}
+
+	let (cell_types, explored) = dungeon::snapshot_cells(world);
+	write_cells(&mut out, &cell_types, &explored);
+
+	let messages = world.messages.borrow();
+	line(&mut out, format!("messages {}", messages.len()));
+	for message in messages.iter() {
+		line(&mut out, message.clone());
+	}
+
+	let ids = world.actors.ids();
+	line(&mut out, format!("actors {}", ids.len()));
+	for id in ids.iter() {
+		write_actor(&mut out, world.actors.get(id).unwrap());
+	}
+
+	// the sides of the player the monsters went for
+	match world.tactics.player_position() {
+		Some(p) => line(&mut out, format!("tactics 1 {} {}", p.x, p.y)),
+		None => line(&mut out, "tactics 0".to_string())
+	}
+	let claims: Vec<(uint, Point)> = world.tactics.claims().into_iter()
+		.filter_map(|(actor, slot)| ids.iter().position(|id| *id == actor).map(|number| (number, slot)))
+		.collect();
+	line(&mut out, format!("claims {}", claims.len()));
+	for &(number, ref slot) in claims.iter() {
+		line(&mut out, format!("claim {} {} {}", number, slot.x, slot.y));
+	}
+
+	line(&mut out, format!("levels {}", world.levels.len()));
+	for level in world.levels.iter() {
+		line(&mut out, format!("level {}", level.depth));
+		write_cells(&mut out, &level.cell_types, &level.explored);
+		line(&mut out, format!("actors {}", level.actors.len()));
+		for actor in level.actors.iter() {
+			write_actor(&mut out, actor);
+		}
+		line(&mut out, "end_level".to_string());
+	}
+
+	line(&mut out, "end".to_string());
+
+	let mut file = try!(File::create(path));
+	file.write_str(out.as_slice())
+}
+
+// Replaces the state of world with the saved game. The world is left untouched if
+// the file can't be parsed.
+pub fn load(world: &mut World, path: &Path) -> Result<(), String> {
+	let contents = match File::open(path).read_to_string() {
+		Ok(contents) => contents,
+		Err(e) => return Err(format!("could not read {}: {}", path.display(), e.desc))
+	};
+	let mut reader = SaveReader::new(contents.as_slice());
+
+	let header = try!(reader.expect(SAVE_MAGIC));
+	let version: uint = try!(reader.number(&header, 0));
+	if version != SAVE_VERSION {
+		return Err(format!("unsupported save version {} (expected {})", version, SAVE_VERSION));
+	}
+
+	let seed: u32 = try!(reader.number(&try!(reader.expect("seed")), 0));
+	let rng_tokens = try!(reader.expect("rng"));
+	let mut rng_state = [0u32, ..4];
+	for i in range(0u, 4) {
+		rng_state[i] = try!(reader.number(&rng_tokens, i));
+	}
+
+	let size = try!(reader.expect("size"));
+	let width: uint = try!(reader.number(&size, 0));
+	let height: uint = try!(reader.number(&size, 1));
+	if width != world.width || height != world.height {
+		return Err(format!("save is for a {}x{} map, world is {}x{}", width, height, world.width, world.height));
+	}
+
+	let player_tokens = try!(reader.expect("player"));
+	let kills: uint = try!(reader.number(&player_tokens, 0));
+	let is_aiming = try!(reader.flag(&player_tokens, 1));
+
+	let depth: uint = try!(reader.number(&try!(reader.expect("depth")), 0));
+	if depth < 1 || depth > dungeon::MAX_DEPTH {
+		return Err(reader.error(format!("depth {} is outside the dungeon", depth)));
+	}
+
+	let mut input_log = Vec::new();
+	for token in try!(reader.expect("input_log")).iter() {
+		match KeyCode::from_token(*token) {
+			Some(key) => input_log.push(key),
+			None => return Err(reader.error(format!("unknown key '{}'", token)))
+		}
+	}
+
+	let start_map = if try!(reader.flag(&try!(reader.expect("start_map")), 0)) {
+		// the map's own text ends on its end line
+		let mut map_lines = Vec::new();
+		loop {
+			let map_line = try!(reader.next());
+			map_lines.push(map_line);
+			if map_line == "end" {
+				break;
+			}
+		}
+		match MapData::from_text(map_lines.connect("\n").as_slice()) {
+			Ok(map) => Some(map),
+			Err(e) => return Err(reader.error(format!("bad start map: {}", e)))
+		}
+	} else {
+		None
+	};
+
+	let (cell_types, explored) = try!(read_cells(&mut reader, width, height));
+
+	let messages_count: uint = try!(reader.number(&try!(reader.expect("messages")), 0));
+	let mut messages = Vec::with_capacity(messages_count);
+	for _ in range(0, messages_count) {
+		messages.push(try!(reader.next()).to_string());
+	}
+
+	let actors_count: uint = try!(reader.number(&try!(reader.expect("actors")), 0));
+	let mut actors = Vec::with_capacity(actors_count);
+	let mut player_count = 0u;
+	for _ in range(0, actors_count) {
+		let actor = try!(read_actor(&mut reader, width, height));
+		if actor.is_player {
+			player_count += 1;
+		}
+		actors.push(actor);
+	}
+	if player_count != 1 {
+		return Err(format!("save must contain exactly one player, found {}", player_count));
+	}
+
+	let tactics_tokens = try!(reader.expect("tactics"));
+	let tactics_position = if try!(reader.flag(&tactics_tokens, 0)) {
+		Some(try!(read_point(&reader, &tactics_tokens, 1, width, height)))
+	} else {
+		None
+	};
+	let claims_count: uint = try!(reader.number(&try!(reader.expect("claims")), 0));
+	let mut claims = Vec::with_capacity(claims_count);
+	for _ in range(0, claims_count) {
+		let claim_tokens = try!(reader.expect("claim"));
+		let number: uint = try!(reader.number(&claim_tokens, 0));
+		if number >= actors_count {
+			return Err(reader.error(format!("claim by unknown actor {}", number)));
+		}
+		claims.push((number, try!(read_point(&reader, &claim_tokens, 1, width, height))));
+	}
+
+	let levels_count: uint = try!(reader.number(&try!(reader.expect("levels")), 0));
+	let mut levels = Vec::with_capacity(levels_count);
+	for _ in range(0, levels_count) {
+		let level_depth: uint = try!(reader.number(&try!(reader.expect("level")), 0));
+		if level_depth < 1 || level_depth > dungeon::MAX_DEPTH || level_depth == depth {
+			return Err(reader.error(format!("bad level depth {}", level_depth)));
+		}
+		let (level_cell_types, level_explored) = try!(read_cells(&mut reader, width, height));
+		let level_actors_count: uint = try!(reader.number(&try!(reader.expect("actors")), 0));
+		let mut level_actors = Vec::with_capacity(level_actors_count);
+		for _ in range(0, level_actors_count) {
+			let actor = try!(read_actor(&mut reader, width, height));
+			if actor.is_player {
+				return Err(reader.error("the player can only be on the current level".to_string()));
+			}
+			level_actors.push(actor);
+		}
+		try!(reader.expect("end_level"));
+		levels.push(Level {depth: level_depth, cell_types: level_cell_types, explored: level_explored, actors: level_actors});
+	}
+
+	try!(reader.expect("end"));
+
+	// everything parsed, now replace the world state
+	world.cleanup();
+	world.seed = seed;
+	world.rng = GameRng::from_state(rng_state);
+
+	for y in range(0, height) {
+		for x in range(0, width) {
+			world.grid[y][x].cell_type = cell_types[y][x].clone();
+			world.grid[y][x].explored = explored[y][x];
+		}
+	}
+
+	world.player_state.kills.set(kills);
+	world.player_state.is_aiming = is_aiming;
+	world.depth = depth;
+	world.levels = levels;
+	world.input_log = input_log;
+	world.start_map = start_map;
+
+	for message in messages.iter() {
+		world.add_message(message.as_slice());
+	}
+
+	// cleanup left a fresh player behind, the saved one replaces it
+	world.actors.clear();
+	let mut ids = Vec::with_capacity(actors_count);
+	for actor in actors.into_iter() {
+		ids.push(world.restore_actor(actor));
+	}
+	let claims = claims.into_iter().map(|(number, slot)| (ids[number].clone(), slot)).collect();
+	world.tactics.restore(tactics_position, claims);
+	world.update_fov();
+
+	Ok(())
+}
+
+pub fn exists(path: &Path) -> bool {
+	path.exists()
+}
+
+pub fn delete(path: &Path) {
+	if path.exists() {
+		let _ = fs::unlink(path);
+	}
+}
+
+// grid and explored rows of a level
+fn write_cells(out: &mut String, cell_types: &Vec<Vec<CellType>>, explored: &Vec<Vec<bool>>) {
+	line(out, "grid".to_string());
+	for row in cell_types.iter() {
+		line(out, row.iter().map(|cell_type| cell_type.glyph()).collect());
+	}
+
+	line(out, "explored".to_string());
+	for row in explored.iter() {
+		line(out, row.iter().map(|flag| if *flag { '1' } else { '0' }).collect());
+	}
+}
+
+fn read_cells(reader: &mut SaveReader, width: uint, height: uint) -> Result<(Vec<Vec<CellType>>, Vec<Vec<bool>>), String> {
+	try!(reader.expect("grid"));
+	let mut cell_types: Vec<Vec<CellType>> = Vec::with_capacity(height);
+	for _ in range(0, height) {
+		let row = try!(reader.next());
+		if row.char_len() != width {
+			return Err(reader.error(format!("grid row should be {} cells wide", width)));
+		}
+		let mut cells = Vec::with_capacity(width);
+		for glyph in row.chars() {
+			match CellType::from_glyph(glyph) {
+				Some(cell_type) => cells.push(cell_type),
+				None => return Err(reader.error(format!("unknown cell glyph '{}'", glyph)))
+			}
+		}
+		cell_types.push(cells);
+	}
+
+	try!(reader.expect("explored"));
+	let mut explored: Vec<Vec<bool>> = Vec::with_capacity(height);
+	for _ in range(0, height) {
+		let row = try!(reader.next());
+		if row.char_len() != width {
+			return Err(reader.error(format!("explored row should be {} cells wide", width)));
+		}
+		let mut cells = Vec::with_capacity(width);
+		for flag in row.chars() {
+			match flag {
+				'1' => cells.push(true),
+				'0' => cells.push(false),
+				_ => return Err(reader.error(format!("bad explored flag '{}'", flag)))
+			}
+		}
+		explored.push(cells);
+	}
+
+	Ok((cell_types, explored))
+}
+
+// the "<x> <y>" at index of tokens, which has to be on the map
+fn read_point<'a>(reader: &SaveReader<'a>, tokens: &Vec<&'a str>, index: uint, width: uint, height: uint) -> Result<Point, String> {
+	let x: uint = try!(reader.number(tokens, index));
+	let y: uint = try!(reader.number(tokens, index + 1));
+	if x >= width || y >= height {
+		return Err(reader.error(format!("position {},{} is outside the map", x, y)));
+	}
+	Ok(Point::new(x, y))
+}
+
+// actor <name>
+// player
+// template <id>
+// position <x> <y>
+// renderable <glyph> <r> <g> <b>
+// health <current> <max>
+// ai <speed> <energy> <brain kind> <brain state...>
+// pickup ammo <amount>
+// inventory <ammo>
+// blocker
+// end_actor
+fn write_actor(out: &mut String, actor: &Actor) {
+	line(out, format!("actor {}", actor.name));
+	if actor.is_player {
+		line(out, "player".to_string());
+	}
+	if let Some(ref template) = actor.template {
+		line(out, format!("template {}", template));
+	}
+	if let Some(ref position) = actor.position {
+		line(out, format!("position {} {}", position.x, position.y));
+	}
+	if let Some(ref renderable) = actor.renderable {
+		let color = &renderable.color;
+		line(out, format!("renderable {} {} {} {}", renderable.glyph, color.r, color.g, color.b));
+	}
+	if let Some(ref health) = actor.health {
+		line(out, format!("health {} {}", health.current, health.max));
+	}
+	if let Some(ref ai) = actor.ai {
+		line(out, format!("ai {} {} {}", ai.speed, ai.energy, ai.brain.save_state().connect(" ")));
+	}
+	if let Some(ref pickup) = actor.pickup {
+		let kind = match pickup.kind {
+			PickupKind::Ammo => "ammo"
+		};
+		line(out, format!("pickup {} {}", kind, pickup.amount));
+	}
+	if let Some(ref inventory) = actor.inventory {
+		line(out, format!("inventory {}", inventory.ammo));
+	}
+	if actor.blocker.is_some() {
+		line(out, "blocker".to_string());
+	}
+	line(out, "end_actor".to_string());
+}
+
+fn read_actor(reader: &mut SaveReader, width: uint, height: uint) -> Result<Actor, String> {
+	let name = try!(reader.rest_of("actor"));
+	let mut actor = Actor::new(name);
+
+	loop {
+		let line = try!(reader.next());
+		let words: Vec<&str> = line.words().collect();
+		if words.is_empty() {
+			return Err(reader.error("empty line in actor".to_string()));
+		}
+		let tokens: Vec<&str> = words.iter().skip(1).map(|word| *word).collect();
+		match words[0] {
+			"end_actor" => break,
+			"player" => {
+				actor.is_player = true;
+			},
+			"template" => {
+				if tokens.is_empty() {
+					return Err(reader.error("missing template id".to_string()));
+				}
+				actor.template = Some(tokens[0].to_string());
+			},
+			"position" => {
+				actor.position = Some(try!(read_point(reader, &tokens, 0, width, height)));
+			},
+			"renderable" => {
+				if tokens.is_empty() || tokens[0].char_len() != 1 {
+					return Err(reader.error("actor glyph must be a single character".to_string()));
+				}
+				let glyph = tokens[0].char_at(0);
+				let color = Color::new(try!(reader.number(&tokens, 1)), try!(reader.number(&tokens, 2)), try!(reader.number(&tokens, 3)));
+				actor.renderable = Some(Renderable {glyph: glyph, color: color});
+			},
+			"health" => {
+				actor.health = Some(Health {current: try!(reader.number(&tokens, 0)), max: try!(reader.number(&tokens, 1))});
+			},
+			"ai" => {
+				let speed: int = try!(reader.number(&tokens, 0));
+				let energy: int = try!(reader.number(&tokens, 1));
+				if tokens.len() < 3 {
+					return Err(reader.error("missing brain kind".to_string()));
+				}
+				let brain = match load_brain(tokens.slice_from(2)) {
+					Ok(brain) => brain,
+					Err(e) => return Err(reader.error(e))
+				};
+				actor.ai = Some(Ai {brain: brain, speed: speed, energy: energy});
+			},
+			"pickup" => {
+				if tokens.is_empty() || tokens[0] != "ammo" {
+					return Err(reader.error("unknown pickup kind".to_string()));
+				}
+				let kind = PickupKind::Ammo;
+				actor.pickup = Some(Pickup {kind: kind, amount: try!(reader.number(&tokens, 1))});
+			},
+			"inventory" => {
+				actor.inventory = Some(Inventory {ammo: try!(reader.number(&tokens, 0))});
+			},
+			"blocker" => {
+				actor.blocker = Some(Blocker);
+			},
+			other => return Err(reader.error(format!("unknown component '{}'", other)))
+		}
+	}
+
+	Ok(actor)
+}
+
+fn line(out: &mut String, text: String) {
+	out.push_str(text.as_slice());
+	out.push('\n');
+}
+
+fn bool_token(value: bool) -> &'static str {
+	if value { "1" } else { "0" }
+}
+
+struct SaveReader<'a> {
+	lines: Vec<&'a str>,
+	line_number: uint
+}
+
+impl<'a> SaveReader<'a> {
+	fn new(contents: &'a str) -> SaveReader<'a> {
+		SaveReader {lines: contents.lines().collect(), line_number: 0}
+	}
+
+	fn error(&self, message: String) -> String {
+		format!("save line {}: {}", self.line_number, message)
+	}
+
+	fn next(&mut self) -> Result<&'a str, String> {
+		if self.line_number >= self.lines.len() {
+			return Err(self.error("unexpected end of file".to_string()));
+		}
+		let line = self.lines[self.line_number];
+		self.line_number += 1;
+		Ok(line)
+	}
+
+	// reads a "<key> <tokens...>" line and returns the tokens
+	fn expect(&mut self, key: &str) -> Result<Vec<&'a str>, String> {
+		let line = try!(self.next());
+		let mut tokens: Vec<&'a str> = line.words().collect();
+		if tokens.is_empty() || tokens[0] != key {
+			return Err(self.error(format!("expected '{}'", key)));
+		}
+		tokens.remove(0);
+		Ok(tokens)
+	}
+
+	// reads a "<key> <free text>" line and returns the text
+	fn rest_of(&mut self, key: &str) -> Result<&'a str, String> {
+		let line = try!(self.next());
+		if !line.starts_with(key) || line.len() < key.len() + 1 {
+			return Err(self.error(format!("expected '{}'", key)));
+		}
+		Ok(line.slice_from(key.len() + 1))
+	}
+
+	fn number<T: FromStr>(&self, tokens: &Vec<&'a str>, index: uint) -> Result<T, String> {
+		if index >= tokens.len() {
+			return Err(self.error("missing value".to_string()));
+		}
+		match tokens[index].parse::<T>() {
+			Some(value) => Ok(value),
+			None => Err(self.error(format!("bad number '{}'", tokens[index])))
+		}
+	}
+
+	fn flag(&self, tokens: &Vec<&'a str>, index: uint) -> Result<bool, String> {
+		let value: uint = try!(self.number(tokens, index));
+		Ok(value != 0)
+	}
+}
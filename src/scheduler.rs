@@ -0,0 +1,149 @@
+// Energy based turn order.
+//
+// Every round each actor gains energy equal to its speed. An actor may act once it
+// has at least ACTION_ENERGY, and acting spends the energy cost of the action. So a
+// speed 200 kobold acts twice for every turn of a speed 100 player, while a firing
+// player (cost 150) falls behind someone who only walks.
+//
+// The most energetic ready actor goes first, ties go to the earlier entry. Nothing
+// here is random, so the same actors always act in the same order.
+
+pub static ACTION_ENERGY: int = 100;
+
+pub trait Scheduled {
+	fn speed(&self) -> int;
+	fn energy(&self) -> int;
+	fn set_energy(&mut self, energy: int);
+	// dead or inert actors never get a turn
+	fn can_act(&self) -> bool;
+}
+
+// index of the entry that acts next, if anyone has enough energy this round
+pub fn next_ready<T: Scheduled>(entries: &[T]) -> Option<uint> {
+	let mut best: Option<uint> = None;
+	for (index, entry) in entries.iter().enumerate() {
+		if !entry.can_act() || entry.energy() < ACTION_ENERGY {
+			continue;
+		}
+		best = match best {
+			Some(best_index) if entries[best_index].energy() >= entry.energy() => Some(best_index),
+			_ => Some(index)
+		};
+	}
+	best
+}
+
+// start a new round. Returns false if nobody could ever become ready.
+pub fn advance<T: Scheduled>(entries: &mut [T]) -> bool {
+	let mut any_can_act = false;
+	for entry in entries.iter_mut() {
+		if entry.can_act() && entry.speed() > 0 {
+			let energy = entry.energy() + entry.speed();
+			entry.set_energy(energy);
+			any_can_act = true;
+		}
+	}
+	any_can_act
+}
+
+// next ready entry, starting new rounds until someone is ready
+pub fn next_to_act<T: Scheduled>(entries: &mut [T]) -> Option<uint> {
+	loop {
+		if let Some(index) = next_ready(&*entries) {
+			return Some(index);
+		}
+		if !advance(entries) {
+			return None;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Scheduled, ACTION_ENERGY, next_to_act};
+	use action::FIRE_COST;
+
+	struct Entry {
+		speed: int,
+		energy: int,
+		alive: bool
+	}
+
+	impl Scheduled for Entry {
+		fn speed(&self) -> int {
+			self.speed
+		}
+
+		fn energy(&self) -> int {
+			self.energy
+		}
+
+		fn set_energy(&mut self, energy: int) {
+			self.energy = energy;
+		}
+
+		fn can_act(&self) -> bool {
+			self.alive
+		}
+	}
+
+	fn entry(speed: int) -> Entry {
+		Entry {speed: speed, energy: 0, alive: true}
+	}
+
+	// who acts in the next turns, each paying what costs says for its index
+	fn turns(entries: &mut [Entry], count: uint, costs: &[int]) -> Vec<uint> {
+		let mut order = Vec::new();
+		for _ in range(0, count) {
+			let index = next_to_act(entries).unwrap();
+			let energy = entries[index].energy - costs[index];
+			entries[index].set_energy(energy);
+			order.push(index);
+		}
+		order
+	}
+
+	fn count(order: &Vec<uint>, index: uint) -> uint {
+		order.iter().filter(|&&acted| acted == index).count()
+	}
+
+	#[test]
+	fn double_speed_acts_twice_as_often() {
+		let mut entries = [entry(100), entry(200)];
+		let order = turns(&mut entries, 300, &[ACTION_ENERGY, ACTION_ENERGY]);
+		assert_eq!(count(&order, 0), 100);
+		assert_eq!(count(&order, 1), 200);
+	}
+
+	#[test]
+	fn ties_go_to_the_earlier_entry() {
+		let mut entries = [entry(100), entry(100), entry(100)];
+		let order = turns(&mut entries, 6, &[ACTION_ENERGY, ACTION_ENERGY, ACTION_ENERGY]);
+		assert!(order == vec![0, 1, 2, 0, 1, 2]);
+	}
+
+	#[test]
+	fn firing_falls_behind() {
+		// the first one fires every turn, the second one walks
+		let mut entries = [entry(100), entry(100)];
+		let order = turns(&mut entries, 500, &[FIRE_COST, ACTION_ENERGY]);
+		assert_eq!(count(&order, 0), 200);
+		assert_eq!(count(&order, 1), 300);
+	}
+
+	#[test]
+	fn nobody_to_act() {
+		let mut empty: [Entry, ..0] = [];
+		assert!(next_to_act(&mut empty).is_none());
+
+		let mut inert = [entry(0), Entry {speed: 100, energy: 500, alive: false}];
+		assert!(next_to_act(&mut inert).is_none());
+	}
+
+	#[test]
+	fn dead_entries_are_skipped() {
+		let mut entries = [Entry {speed: 100, energy: 0, alive: false}, entry(100)];
+		let order = turns(&mut entries, 3, &[ACTION_ENERGY, ACTION_ENERGY]);
+		assert!(order == vec![1, 1, 1]);
+	}
+}
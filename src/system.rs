@@ -0,0 +1,33 @@
+use util::Point;
+use actor::Renderable;
+use world::World;
+
+// Systems work on the actors that have the components they need and skip the
+// rest. Scheduling lives with the ai component, see World::next_to_act.
+
+pub struct Sprite {
+	pub position: Point,
+	pub renderable: Renderable
+}
+
+// everything with a position and a renderable, in draw order
+pub fn sprites(world: &World) -> Vec<Sprite> {
+	let mut sprites = Vec::new();
+	for id in world.actors.ids().iter() {
+		let actor = world.actors.get(id).unwrap();
+		if let (Some(position), Some(renderable)) = (actor.position.as_ref(), actor.renderable.as_ref()) {
+			sprites.push(Sprite {position: position.clone(), renderable: renderable.clone()});
+		}
+	}
+	sprites
+}
+
+// removes actors whose health ran out and frees their cells
+pub fn sweep(world: &mut World) {
+	for id in world.actors.ids().iter() {
+		let dead = world.actors.get(id).map_or(false, |actor| actor.health.is_some() && !actor.is_alive());
+		if dead {
+			world.remove_actor(id);
+		}
+	}
+}
@@ -0,0 +1,345 @@
+use util::{Point, Direction};
+use world::World;
+use arena::ActorId;
+use action;
+use action::BoxedAction;
+use pathfinding::{PathOptions, neighbours};
+use behaviour;
+
+use std::collections::RingBuf;
+
+// how close to the player monsters start coordinating
+static SURROUND_RADIUS: uint = 6;
+// how many cells the search for a way to a slot may look at
+static SLOT_SEARCH_BUDGET: uint = 300;
+// how many cells the search for a place to hold back at may look at
+static HOLD_BACK_BUDGET: uint = 200;
+// a cell with this many walkable neighbours is out of any corridor
+static OPEN_NEIGHBOURS: uint = 3;
+
+// a cell next to the player that one monster is heading for
+struct Claim {
+	actor: ActorId,
+	slot: Point
+}
+
+// Which monster goes for which side of the player. Claims only hold while the
+// player stays put, they are handed out again once it moves.
+pub struct Tactics {
+	player_position: Option<Point>,
+	claims: Vec<Claim>
+}
+
+impl Tactics {
+	pub fn new() -> Tactics {
+		Tactics {player_position: None, claims: Vec::new()}
+	}
+
+	pub fn clear(&mut self) {
+		self.player_position = None;
+		self.claims.clear();
+	}
+
+	// the player position the claims were handed out for
+	pub fn player_position(&self) -> Option<Point> {
+		self.player_position.clone()
+	}
+
+	// who claimed which side, in the order the claims were made
+	pub fn claims(&self) -> Vec<(ActorId, Point)> {
+		self.claims.iter().map(|claim| (claim.actor.clone(), claim.slot.clone())).collect()
+	}
+
+	// puts back claims taken from claims(), e.g. when loading a saved game
+	pub fn restore(&mut self, player_position: Option<Point>, claims: Vec<(ActorId, Point)>) {
+		self.player_position = player_position;
+		self.claims = claims.into_iter().map(|(actor, slot)| Claim {actor: actor, slot: slot}).collect();
+	}
+
+	fn slot_of(&self, actor: &ActorId) -> Option<Point> {
+		self.claims.iter().find(|claim| claim.actor == *actor).map(|claim| claim.slot.clone())
+	}
+
+	fn is_claimed(&self, slot: &Point) -> bool {
+		self.claims.iter().any(|claim| claim.slot == *slot)
+	}
+
+	fn release(&mut self, actor: &ActorId) {
+		self.claims.retain(|claim| claim.actor != *actor);
+	}
+}
+
+// The move of a monster close to the player: head for a free side of the player
+// so the pack surrounds it, or hold back out of the way when every side is
+// taken. None when the monster is too far away to take part or can't get
+// any closer.
+pub fn surround(world: &mut World, actor: &ActorId, position: &Point) -> Option<BoxedAction> {
+	let player_position = world.get_player_position();
+	if world.is_game_over() || position.distance_to(&player_position) > SURROUND_RADIUS {
+		return None;
+	}
+	refresh(world, &player_position);
+
+	let mut options = PathOptions::new();
+	options.avoid_actors = true;
+	options.max_nodes = SLOT_SEARCH_BUDGET;
+
+	if let Some(slot) = world.tactics.slot_of(actor) {
+		if let Some(step) = first_step(world, position, &slot, &options) {
+			return Some(step);
+		}
+		// the way there got blocked, try another side
+		world.tactics.release(actor);
+	}
+
+	let slots = free_slots(world, &player_position, position);
+	if slots.is_empty() {
+		return Some(hold_back(world, position, &options));
+	}
+	for slot in slots.into_iter() {
+		if let Some(step) = first_step(world, position, &slot, &options) {
+			world.tactics.claims.push(Claim {actor: actor.clone(), slot: slot});
+			return Some(step);
+		}
+	}
+
+	// no way to a free side, head for the player like a lone monster would
+	let mut path = RingBuf::new();
+	if behaviour::step_toward_player(position, &mut path, world) {
+		return behaviour::follow_path(&mut path, world);
+	}
+	None
+}
+
+// Every side is taken. Waiting in a corridor would jam the way for the others,
+// so step aside to the nearest open cell off the way to the player and wait
+// there.
+fn hold_back(world: &mut World, position: &Point, options: &PathOptions) -> BoxedAction {
+	let route = route_to_player(world, position);
+	match nearest_open_cell(world, position, route.as_slice()) {
+		Some(ref spot) if *spot != *position => {
+			first_step(world, position, spot, options).unwrap_or_else(|| action::wait())
+		},
+		_ => action::wait()
+	}
+}
+
+// the cells the shared distance map leads along from position to the player
+fn route_to_player(world: &mut World, position: &Point) -> Vec<Point> {
+	let to_player = &world.nav_maps().to_player;
+	let mut route = Vec::new();
+	let mut current = position.clone();
+	loop {
+		match to_player.downhill(&current).into_iter().next() {
+			Some(next) => {
+				route.push(next.clone());
+				current = next;
+			},
+			None => break
+		}
+	}
+	route
+}
+
+// the closest cell to position, in steps, that is neither in a corridor nor on
+// the route, position itself included
+fn nearest_open_cell(world: &World, position: &Point, route: &[Point]) -> Option<Point> {
+	let mut seen = Vec::from_elem(world.height, Vec::from_elem(world.width, false));
+	let mut open = RingBuf::new();
+	seen[position.y][position.x] = true;
+	open.push_back(position.clone());
+
+	let mut visited = 0u;
+	while let Some(p) = open.pop_front() {
+		let free = p == *position || world.is_walkable(&p);
+		if free && !route.contains(&p) && is_open(world, &p) {
+			return Some(p);
+		}
+		visited += 1;
+		if visited >= HOLD_BACK_BUDGET {
+			break;
+		}
+		for next in neighbours(&p, world.width, world.height, false).into_iter() {
+			if !seen[next.y][next.x] && world.cell_type_at(&next).is_walkable() {
+				seen[next.y][next.x] = true;
+				open.push_back(next);
+			}
+		}
+	}
+	None
+}
+
+// enough walkable neighbours that others can get past
+fn is_open(world: &World, p: &Point) -> bool {
+	let walkable = neighbours(p, world.width, world.height, false).iter()
+		.filter(|next| world.cell_type_at(*next).is_walkable())
+		.count();
+	walkable >= OPEN_NEIGHBOURS
+}
+
+// drops claims of a player position that's out of date and of monsters that
+// are gone or have wandered off, e.g. to flee or regroup
+fn refresh(world: &mut World, player_position: &Point) {
+	if world.tactics.player_position.as_ref() != Some(player_position) {
+		world.tactics.clear();
+		world.tactics.player_position = Some(player_position.clone());
+		return;
+	}
+	let gone: Vec<ActorId> = world.tactics.claims.iter()
+		.filter(|claim| {
+			match world.actors.get(&claim.actor).and_then(|actor| actor.position.as_ref()) {
+				Some(p) => p.distance_to(player_position) > SURROUND_RADIUS,
+				None => true
+			}
+		})
+		.map(|claim| claim.actor.clone())
+		.collect();
+	for actor in gone.iter() {
+		world.tactics.release(actor);
+	}
+}
+
+// cells next to the player nobody has claimed, closest to position first
+fn free_slots(world: &World, player_position: &Point, position: &Point) -> Vec<Point> {
+	let mut slots: Vec<(uint, Point)> = Vec::new();
+	for direction in [Direction::North, Direction::South, Direction::East, Direction::West].iter() {
+		let mut slot = player_position.clone();
+		slot.translate(direction);
+		if !world.is_valid(&slot) || world.tactics.is_claimed(&slot) {
+			continue;
+		}
+		if world.is_walkable(&slot) || world.is_closed_door(&slot) {
+			slots.push((slot.distance_to(position), slot));
+		}
+	}
+	slots.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+	slots.into_iter().map(|(_, slot)| slot).collect()
+}
+
+// the action that takes position one step along a path to slot
+fn first_step(world: &World, position: &Point, slot: &Point, options: &PathOptions) -> Option<BoxedAction> {
+	let path = match world.find_path(position, slot, options) {
+		Some(path) => path,
+		None => return None
+	};
+	match path.into_iter().next() {
+		Some(step) => {
+			if world.is_walkable(&step) {
+				Some(action::move_to(&step))
+			} else if world.is_closed_door(&step) {
+				Some(action::open_door(&step))
+			} else {
+				None
+			}
+		},
+		// already there
+		None => Some(action::wait())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::surround;
+	use util::Point;
+	use world::{World, CellType};
+	use arena::ActorId;
+	use actor::{Actor, Blocker};
+	use action::perform;
+
+	// a world drawn with cell glyphs, '@' is the player and 'k' a kobold. The
+	// kobolds come back in reading order.
+	fn world_from(rows: &[&str]) -> (World, Vec<ActorId>) {
+		let mut world = World::new(rows[0].len(), rows.len());
+		let mut kobolds = Vec::new();
+		for (y, row) in rows.iter().enumerate() {
+			for (x, glyph) in row.chars().enumerate() {
+				let p = Point::new(x, y);
+				match glyph {
+					'@' => {
+						let player = world.player.clone();
+						world.set_actor_position(&player, &p);
+					},
+					'k' => {
+						let mut kobold = Actor::new("kobold");
+						kobold.blocker = Some(Blocker);
+						kobolds.push(world.add_actor(kobold, p));
+					},
+					_ => world.set_cell_type(&p, CellType::from_glyph(glyph).unwrap())
+				}
+			}
+		}
+		(world, kobolds)
+	}
+
+	fn position_of(world: &World, id: &ActorId) -> Point {
+		world.actors.get(id).unwrap().get_position().clone()
+	}
+
+	// lets id take the move surround picks for it
+	fn take_turn(world: &mut World, id: &ActorId) {
+		let position = position_of(world, id);
+		let step = surround(world, id, &position).expect("close enough to take part");
+		perform(&step, id, world);
+	}
+
+	fn slot_of(world: &World, id: &ActorId) -> Option<Point> {
+		world.tactics.claims().into_iter().find(|&(ref actor, _)| *actor == *id).map(|(_, slot)| slot)
+	}
+
+	#[test]
+	fn kobolds_claim_different_sides() {
+		let (mut world, kobolds) = world_from(&[
+			"..kk...",
+			".......",
+			".......",
+			"...@...",
+			".......",
+		]);
+		take_turn(&mut world, &kobolds[1]);
+		take_turn(&mut world, &kobolds[0]);
+
+		// both are closest to the north side, the second one goes west
+		assert!(slot_of(&world, &kobolds[1]) == Some(Point::new(3, 2)));
+		assert!(slot_of(&world, &kobolds[0]) == Some(Point::new(2, 3)));
+		assert!(position_of(&world, &kobolds[1]) == Point::new(3, 1));
+		assert!(position_of(&world, &kobolds[0]) == Point::new(2, 1));
+	}
+
+	#[test]
+	fn kobolds_hold_back_off_the_corridor() {
+		let (mut world, kobolds) = world_from(&[
+			"##########",
+			"#...######",
+			"#....k.k.@",
+			"#...######",
+			"##########",
+		]);
+		// the only side goes to the kobold in front
+		take_turn(&mut world, &kobolds[1]);
+		assert!(slot_of(&world, &kobolds[1]) == Some(Point::new(8, 2)));
+
+		// the one behind backs out of the corridor into the room and waits there
+		take_turn(&mut world, &kobolds[0]);
+		assert!(position_of(&world, &kobolds[0]) == Point::new(4, 2));
+		take_turn(&mut world, &kobolds[0]);
+		assert!(position_of(&world, &kobolds[0]) == Point::new(3, 2));
+		take_turn(&mut world, &kobolds[0]);
+		assert!(position_of(&world, &kobolds[0]) == Point::new(3, 2));
+		assert!(slot_of(&world, &kobolds[0]).is_none());
+	}
+
+	#[test]
+	fn kobolds_approach_when_no_side_can_be_reached() {
+		// the free side to the north can only be reached past the player
+		let (mut world, kobolds) = world_from(&[
+			"#######",
+			"####.##",
+			"#k.k@##",
+			"#######",
+		]);
+		take_turn(&mut world, &kobolds[0]);
+
+		assert!(position_of(&world, &kobolds[0]) == Point::new(2, 2));
+		assert!(slot_of(&world, &kobolds[0]).is_none());
+	}
+}
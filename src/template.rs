@@ -0,0 +1,294 @@
+use util::{Color, GameRng};
+use actor::{Actor, Renderable, Health, Ai, Pickup, PickupKind, Inventory, Blocker, new_brain};
+
+use std::io::File;
+use std::rand::Rng;
+use std::str::FromStr;
+
+// shipped with the binary, used when there is no data/actors.txt to load
+static BUILTIN_TEMPLATES: &'static str = include_str!("../data/actors.txt");
+
+pub static TEMPLATES_FILE: &'static str = "data/actors.txt";
+
+// what the level generator places a template as
+#[deriving(Clone, PartialEq)]
+pub enum Role {
+	Monster,
+	Spawner,
+	Item,
+	// only placed by other means, e.g. spawned by a generator
+	Unplaced
+}
+
+pub struct ActorTemplate {
+	pub id: String,
+	pub name: String,
+	pub glyph: char,
+	pub color: Color,
+	pub health: Option<int>,
+	pub speed: int,
+	pub brain: Option<String>,
+	pub spawns: Option<String>,
+	// what a tree brain is made of, see behaviour::parse
+	pub behaviour: Option<String>,
+	pub blocker: bool,
+	pub pickup: Option<(PickupKind, uint)>,
+	// rounds carried, for monsters that shoot
+	pub ammo: Option<uint>,
+	pub role: Role,
+	pub spawn_weight: uint,
+	// shallowest dungeon level the generator places it on
+	pub min_depth: uint
+}
+
+impl ActorTemplate {
+	fn new(id: &str) -> ActorTemplate {
+		ActorTemplate {
+			id: id.to_string(),
+			name: id.to_string(),
+			glyph: '?',
+			color: Color::white(),
+			health: None,
+			speed: 100,
+			brain: None,
+			spawns: None,
+			behaviour: None,
+			blocker: false,
+			pickup: None,
+			ammo: None,
+			role: Role::Unplaced,
+			spawn_weight: 0,
+			min_depth: 1
+		}
+	}
+
+	// whether the level generator places it as role at depth
+	pub fn is_placed(&self, role: &Role, depth: uint) -> bool {
+		self.role == *role && self.min_depth <= depth
+	}
+
+	pub fn build(&self) -> Actor {
+		let mut actor = Actor::new(self.name.as_slice());
+		actor.template = Some(self.id.clone());
+		actor.renderable = Some(Renderable {glyph: self.glyph, color: self.color.clone()});
+		if let Some(health) = self.health {
+			actor.health = Some(Health {current: health, max: health});
+		}
+		if let Some(ref brain) = self.brain {
+			// checked when the templates were loaded
+			let spawns = self.spawns.as_ref().map(|spawns| spawns.as_slice());
+			let behaviour = self.behaviour.as_ref().map(|behaviour| behaviour.as_slice());
+			let brain = new_brain(brain.as_slice(), spawns, behaviour).unwrap();
+			actor.ai = Some(Ai {brain: brain, speed: self.speed, energy: 0});
+		}
+		if let Some((ref kind, amount)) = self.pickup {
+			actor.pickup = Some(Pickup {kind: kind.clone(), amount: amount});
+		}
+		if let Some(ammo) = self.ammo {
+			actor.inventory = Some(Inventory {ammo: ammo});
+		}
+		if self.blocker {
+			actor.blocker = Some(Blocker);
+		}
+		actor
+	}
+}
+
+pub struct TemplateRegistry {
+	templates: Vec<ActorTemplate>
+}
+
+impl TemplateRegistry {
+	pub fn builtin() -> TemplateRegistry {
+		match TemplateRegistry::parse(BUILTIN_TEMPLATES, "builtin actors.txt") {
+			Ok(registry) => registry,
+			Err(e) => panic!("{}", e)
+		}
+	}
+
+	pub fn load(path: &Path) -> Result<TemplateRegistry, String> {
+		let contents = match File::open(path).read_to_string() {
+			Ok(contents) => contents,
+			Err(e) => return Err(format!("could not read {}: {}", path.display(), e.desc))
+		};
+		TemplateRegistry::parse(contents.as_slice(), format!("{}", path.display()).as_slice())
+	}
+
+	// origin names the source in error messages, e.g. "data/actors.txt:12: ..."
+	pub fn parse(source: &str, origin: &str) -> Result<TemplateRegistry, String> {
+		let mut templates: Vec<ActorTemplate> = Vec::new();
+
+		for (index, raw_line) in source.lines().enumerate() {
+			let line_number = index + 1;
+			let error = |message: String| -> String { format!("{}:{}: {}", origin, line_number, message) };
+
+			let line = raw_line.trim();
+			if line.is_empty() || line.starts_with("#") {
+				continue;
+			}
+
+			if line.starts_with("[") {
+				if !line.ends_with("]") || line.len() < 3 {
+					return Err(error(format!("bad template header '{}'", line)));
+				}
+				let id = line.slice(1, line.len() - 1).trim();
+				if templates.iter().any(|template| template.id.as_slice() == id) {
+					return Err(error(format!("template '{}' is defined twice", id)));
+				}
+				templates.push(ActorTemplate::new(id));
+				continue;
+			}
+
+			let template = match templates.last_mut() {
+				Some(template) => template,
+				None => return Err(error("key outside of a [template] section".to_string()))
+			};
+
+			let (key, value) = match line.find('=') {
+				Some(split) => (line.slice_to(split).trim(), line.slice_from(split + 1).trim()),
+				None => return Err(error(format!("expected 'key = value', found '{}'", line)))
+			};
+
+			match set_field(template, key, value) {
+				Ok(()) => {},
+				Err(e) => return Err(error(e))
+			}
+		}
+
+		let registry = TemplateRegistry {templates: templates};
+		try!(registry.check(origin));
+		Ok(registry)
+	}
+
+	// checks that only make sense once every template is known
+	fn check(&self, origin: &str) -> Result<(), String> {
+		for template in self.templates.iter() {
+			if let Some(ref brain) = template.brain {
+				let spawns = template.spawns.as_ref().map(|spawns| spawns.as_slice());
+				let behaviour = template.behaviour.as_ref().map(|behaviour| behaviour.as_slice());
+				if let Err(e) = new_brain(brain.as_slice(), spawns, behaviour) {
+					return Err(format!("{}: template '{}': {}", origin, template.id, e));
+				}
+			}
+			if let Some(ref spawns) = template.spawns {
+				if self.get(spawns.as_slice()).is_none() {
+					return Err(format!("{}: template '{}' spawns unknown template '{}'", origin, template.id, spawns));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	pub fn get(&self, id: &str) -> Option<&ActorTemplate> {
+		self.templates.iter().find(|template| template.id.as_slice() == id)
+	}
+
+	pub fn build(&self, id: &str) -> Option<Actor> {
+		self.get(id).map(|template| template.build())
+	}
+
+	pub fn iter(&self) -> ::std::slice::Items<ActorTemplate> {
+		self.templates.iter()
+	}
+
+	// weighted random pick among the templates placed as role at depth
+	pub fn pick(&self, role: Role, depth: uint, rng: &mut GameRng) -> Option<&ActorTemplate> {
+		let total = self.templates.iter()
+			.filter(|template| template.is_placed(&role, depth))
+			.fold(0u, |sum, template| sum + template.spawn_weight);
+		if total == 0 {
+			return None;
+		}
+
+		let mut roll = rng.gen_range(0u, total);
+		for template in self.templates.iter().filter(|template| template.is_placed(&role, depth)) {
+			if roll < template.spawn_weight {
+				return Some(template);
+			}
+			roll -= template.spawn_weight;
+		}
+		None
+	}
+}
+
+fn set_field(template: &mut ActorTemplate, key: &str, value: &str) -> Result<(), String> {
+	match key {
+		"name" => {
+			if value.is_empty() {
+				return Err("name can't be empty".to_string());
+			}
+			template.name = value.to_string();
+		},
+		"glyph" => {
+			if value.char_len() != 1 {
+				return Err(format!("glyph must be a single character, found '{}'", value));
+			}
+			template.glyph = value.char_at(0);
+		},
+		"color" => {
+			let parts: Vec<&str> = value.words().collect();
+			if parts.len() != 3 {
+				return Err(format!("color must be 'r g b', found '{}'", value));
+			}
+			template.color = Color::new(try!(number(parts[0], "color")), try!(number(parts[1], "color")), try!(number(parts[2], "color")));
+		},
+		"health" => {
+			let health: int = try!(number(value, key));
+			if health <= 0 {
+				return Err("health must be positive".to_string());
+			}
+			template.health = Some(health);
+		},
+		"speed" => {
+			let speed: int = try!(number(value, key));
+			if speed < 0 {
+				return Err("speed can't be negative".to_string());
+			}
+			template.speed = speed;
+		},
+		"brain" => { template.brain = Some(value.to_string()); },
+		"spawns" => { template.spawns = Some(value.to_string()); },
+		"behaviour" => { template.behaviour = Some(value.to_string()); },
+		"blocker" => {
+			template.blocker = match value {
+				"true" => true,
+				"false" => false,
+				_ => return Err(format!("blocker must be true or false, found '{}'", value))
+			};
+		},
+		"pickup" => {
+			let parts: Vec<&str> = value.words().collect();
+			if parts.len() != 2 || parts[0] != "ammo" {
+				return Err(format!("pickup must be 'ammo <amount>', found '{}'", value));
+			}
+			template.pickup = Some((PickupKind::Ammo, try!(number(parts[1], key))));
+		},
+		"ammo" => { template.ammo = Some(try!(number(value, key))); },
+		"role" => {
+			template.role = match value {
+				"monster" => Role::Monster,
+				"spawner" => Role::Spawner,
+				"item" => Role::Item,
+				"none" => Role::Unplaced,
+				_ => return Err(format!("unknown role '{}'", value))
+			};
+		},
+		"spawn_weight" => { template.spawn_weight = try!(number(value, key)); },
+		"min_depth" => {
+			let min_depth: uint = try!(number(value, key));
+			if min_depth == 0 {
+				return Err("min_depth starts at 1".to_string());
+			}
+			template.min_depth = min_depth;
+		},
+		_ => return Err(format!("unknown key '{}'", key))
+	}
+	Ok(())
+}
+
+fn number<T: FromStr>(value: &str, key: &str) -> Result<T, String> {
+	match value.parse::<T>() {
+		Some(number) => Ok(number),
+		None => Err(format!("{} expects a number, found '{}'", key, value))
+	}
+}
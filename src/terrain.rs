@@ -0,0 +1,173 @@
+use util::Point;
+use world::{World, CellType};
+
+use std::rand::Rng;
+
+static MAX_DOORS: uint = 10;
+// chance in percent that a chokepoint gets a door
+static DOOR_CHANCE: uint = 25;
+
+// Sprinkles doors, water, rubble and, further down, lava and chasms over the
+// floor of a freshly generated level. Vault cells are left alone, and nothing is
+// placed that would cut one part of the level off from another.
+pub fn add_features(world: &mut World, in_vault: &Vec<Vec<bool>>) {
+	add_doors(world, in_vault);
+
+	let depth = world.depth;
+	let pools = 2 + world.rng.gen_range(0u, 3);
+	for _ in range(0, pools) {
+		add_blob(world, in_vault, CellType::Water, 25);
+	}
+	for _ in range(0, 3u) {
+		add_blob(world, in_vault, CellType::Rubble, 8);
+	}
+	if depth >= 2 {
+		for _ in range(0, depth - 1) {
+			add_blob(world, in_vault, CellType::Lava, 12);
+		}
+		add_blob(world, in_vault, CellType::Chasm, 20);
+	}
+}
+
+// doors go into one cell wide gaps between two walls
+fn add_doors(world: &mut World, in_vault: &Vec<Vec<bool>>) {
+	let mut doors = 0u;
+	for y in range(1, world.height - 1) {
+		for x in range(1, world.width - 1) {
+			if doors >= MAX_DOORS {
+				return;
+			}
+			if in_vault[y][x] || world.get_cell(x, y).cell_type != CellType::Floor {
+				continue;
+			}
+
+			let (wall, floor) = (CellType::Wall, CellType::Floor);
+			let across = is(world, x - 1, y, &wall) && is(world, x + 1, y, &wall) && is(world, x, y - 1, &floor) && is(world, x, y + 1, &floor);
+			let along = is(world, x, y - 1, &wall) && is(world, x, y + 1, &wall) && is(world, x - 1, y, &floor) && is(world, x + 1, y, &floor);
+			if (across || along) && world.rng.gen_range(0u, 100) < DOOR_CHANCE {
+				world.set_cell_type(&Point::new(x, y), CellType::DoorClosed);
+				doors += 1;
+			}
+		}
+	}
+}
+
+fn is(world: &World, x: uint, y: uint, cell_type: &CellType) -> bool {
+	world.get_cell(x, y).cell_type == *cell_type
+}
+
+// Turns a random walk of up to size floor cells into cell_type. Blobs that
+// can't be crossed are undone if they split the level.
+fn add_blob(world: &mut World, in_vault: &Vec<Vec<bool>>, cell_type: CellType, size: uint) {
+	let mut start = None;
+	for _ in range(0, 20u) {
+		let x = world.rng.gen_range(1u, world.width - 1);
+		let y = world.rng.gen_range(1u, world.height - 1);
+		if world.get_cell(x, y).cell_type == CellType::Floor && !in_vault[y][x] {
+			start = Some(Point::new(x, y));
+			break;
+		}
+	}
+	let mut position = match start {
+		Some(position) => position,
+		None => return
+	};
+
+	let mut changed = Vec::new();
+	for _ in range(0, size * 2) {
+		if changed.len() >= size {
+			break;
+		}
+		if world.get_cell(position.x, position.y).cell_type == CellType::Floor && !in_vault[position.y][position.x] {
+			world.set_cell_type(&position, cell_type.clone());
+			changed.push(position.clone());
+		}
+
+		let mut next = position.clone();
+		match world.rng.gen_range(0u, 4) {
+			0 => next.y -= 1,
+			1 => next.y += 1,
+			2 => next.x -= 1,
+			_ => next.x += 1
+		}
+		// keep off the border
+		if next.x > 0 && next.y > 0 && next.x < world.width - 1 && next.y < world.height - 1 {
+			position = next;
+		}
+	}
+
+	if !is_crossable(&cell_type) && !is_connected(world) {
+		for p in changed.iter() {
+			world.set_cell_type(p, CellType::Floor);
+		}
+	}
+}
+
+// lava can be walked over, but nobody should have to
+fn is_crossable(cell_type: &CellType) -> bool {
+	cell_type.is_passable() && *cell_type != CellType::Lava
+}
+
+// whether every crossable cell can be reached from every other
+fn is_connected(world: &World) -> bool {
+	let mut total = 0u;
+	let mut start = None;
+	for y in range(0, world.height) {
+		for x in range(0, world.width) {
+			if is_crossable(&world.get_cell(x, y).cell_type) {
+				total += 1;
+				if start.is_none() {
+					start = Some(Point::new(x, y));
+				}
+			}
+		}
+	}
+	let start = match start {
+		Some(start) => start,
+		None => return true
+	};
+
+	let mut seen = Vec::from_elem(world.height, Vec::from_elem(world.width, false));
+	let mut open = vec![start.clone()];
+	seen[start.y][start.x] = true;
+	let mut reached = 0u;
+	while let Some(p) = open.pop() {
+		reached += 1;
+		let neighbours = [(p.x, p.y - 1), (p.x, p.y + 1), (p.x - 1, p.y), (p.x + 1, p.y)];
+		for &(nx, ny) in neighbours.iter() {
+			if nx < world.width && ny < world.height && !seen[ny][nx] && is_crossable(&world.get_cell(nx, ny).cell_type) {
+				seen[ny][nx] = true;
+				open.push(Point::new(nx, ny));
+			}
+		}
+	}
+	reached == total
+}
+
+#[cfg(test)]
+mod test {
+	use super::is_connected;
+	use util::Point;
+	use world::{World, CellType};
+
+	// a wall across the middle of a small level with one gap in it
+	fn split_world(gap: CellType) -> World {
+		let mut world = World::new(5, 5);
+		for x in range(0, 5u) {
+			world.set_cell_type(&Point::new(x, 2), CellType::Wall);
+		}
+		world.set_cell_type(&Point::new(2, 2), gap);
+		world
+	}
+
+	#[test]
+	fn floor_and_water_connect() {
+		assert!(is_connected(&split_world(CellType::Floor)));
+		assert!(is_connected(&split_world(CellType::Water)));
+	}
+
+	#[test]
+	fn lava_does_not_connect() {
+		assert!(!is_connected(&split_world(CellType::Lava)));
+	}
+}
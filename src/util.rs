@@ -1,8 +1,7 @@
-extern crate tcod;
-
-use std::rand;
+use std::rand::Rng;
 use std::num::SignedInt;
 
+#[deriving(Clone, PartialEq)]
 pub enum Direction {
 	North,
 	East,
@@ -13,8 +12,8 @@ pub enum Direction {
 static DIRECTIONS: [Direction, ..4] = [Direction::North, Direction::South, Direction::East, Direction::West];
 
 impl Direction {
-	pub fn random_direction() -> Direction {
-		match rand::random::<uint>()%4 {
+	pub fn random_direction(rng: &mut GameRng) -> Direction {
+		match rng.gen_range(0u, 4) {
 			0 => { Direction::North },
 			1 => { Direction::South },
 			2 => { Direction::East },
@@ -24,6 +23,45 @@ impl Direction {
 	}	
 }
 
+// xorshift128, kept in-tree so a seed reproduces the same run everywhere
+pub struct GameRng {
+	x: u32,
+	y: u32,
+	z: u32,
+	w: u32
+}
+
+impl GameRng {
+	pub fn new(seed: u32) -> GameRng {
+		let mut rng = GameRng {x: 123456789 ^ seed, y: 362436069, z: 521288629, w: 88675123};
+		// discard the first few values, they correlate with the seed
+		for _ in range(0u, 16) {
+			rng.next_u32();
+		}
+		rng
+	}
+
+	pub fn state(&self) -> [u32, ..4] {
+		[self.x, self.y, self.z, self.w]
+	}
+
+	pub fn from_state(state: [u32, ..4]) -> GameRng {
+		GameRng {x: state[0], y: state[1], z: state[2], w: state[3]}
+	}
+}
+
+impl Rng for GameRng {
+	fn next_u32(&mut self) -> u32 {
+		let t = self.x ^ (self.x << 11);
+		self.x = self.y;
+		self.y = self.z;
+		self.z = self.w;
+		self.w = self.w ^ (self.w >> 19) ^ t ^ (t >> 8);
+		self.w
+	}
+}
+
+#[deriving(Clone, PartialEq)]
 pub struct Point {
     pub x: uint,
     pub y: uint
@@ -66,13 +104,15 @@ impl Point {
 	}
 }	
 
+#[deriving(Clone)]
 pub struct Color {
-	r: u8,
-	g: u8,
-	b: u8
+	pub r: u8,
+	pub g: u8,
+	pub b: u8
 }
 
 impl Color {
+	pub fn new(r: u8, g: u8, b: u8) -> Color { Color {r: r, g: g, b: b} }
 	pub fn red() -> Color { Color {r:255, g: 0, b: 0} }
 	pub fn green() -> Color { Color {r:0, g: 255, b: 0} }
 	pub fn black() -> Color { Color {r:0, g: 0, b: 0} }
@@ -80,8 +120,5 @@ impl Color {
 	pub fn panel_green() -> Color { Color {r:76, g:153, b:0} }
 	pub fn light_blue() -> Color { Color {r:0, g:102, b:204} }
 	pub fn white() -> Color { Color {r:255, g:255, b:255} }
-
-	pub fn to_tcod_color(&self) -> tcod::Color {
-		tcod::Color::new(self.r, self.g, self.b)
-	}
+	pub fn dark_grey() -> Color { Color {r:64, g:64, b:64} }
 }
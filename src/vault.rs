@@ -0,0 +1,250 @@
+use util::GameRng;
+use template::TemplateRegistry;
+
+use std::io::File;
+use std::rand::Rng;
+
+// shipped with the binary, used when there is no data/vaults.txt to load
+static BUILTIN_VAULTS: &'static str = include_str!("../data/vaults.txt");
+
+pub static VAULTS_FILE: &'static str = "data/vaults.txt";
+
+#[deriving(Clone, PartialEq)]
+pub enum VaultCell {
+	Wall,
+	Floor,
+	// keeps whatever the level generator put there
+	Untouched,
+	// floor with an actor built from the template
+	Actor(String)
+}
+
+pub struct Vault {
+	pub id: String,
+	pub weight: uint,
+	pub min_depth: uint,
+	pub rows: Vec<Vec<VaultCell>>
+}
+
+impl Vault {
+	pub fn width(&self) -> uint {
+		self.rows[0].len()
+	}
+
+	pub fn height(&self) -> uint {
+		self.rows.len()
+	}
+}
+
+// vault being read, the legend has to be known before the map rows
+struct VaultBuilder {
+	id: String,
+	weight: uint,
+	min_depth: uint,
+	legend: Vec<(char, String)>,
+	rows: Vec<Vec<VaultCell>>,
+	in_map: bool,
+	finished: bool
+}
+
+pub struct VaultRegistry {
+	vaults: Vec<Vault>
+}
+
+impl VaultRegistry {
+	pub fn builtin() -> VaultRegistry {
+		match VaultRegistry::parse(BUILTIN_VAULTS, "builtin vaults.txt") {
+			Ok(registry) => registry,
+			Err(e) => panic!("{}", e)
+		}
+	}
+
+	pub fn load(path: &Path) -> Result<VaultRegistry, String> {
+		let contents = match File::open(path).read_to_string() {
+			Ok(contents) => contents,
+			Err(e) => return Err(format!("could not read {}: {}", path.display(), e.desc))
+		};
+		VaultRegistry::parse(contents.as_slice(), format!("{}", path.display()).as_slice())
+	}
+
+	// origin names the source in error messages, e.g. "data/vaults.txt:12: ..."
+	pub fn parse(source: &str, origin: &str) -> Result<VaultRegistry, String> {
+		let mut builders: Vec<VaultBuilder> = Vec::new();
+
+		for (index, raw_line) in source.lines().enumerate() {
+			let line_number = index + 1;
+			let error = |message: String| -> String { format!("{}:{}: {}", origin, line_number, message) };
+
+			// map rows are taken as they are, # is a wall there and not a comment
+			if let Some(builder) = builders.last_mut() {
+				if builder.in_map {
+					let row = raw_line.trim_right();
+					if row == "end" {
+						if builder.rows.is_empty() {
+							return Err(error(format!("vault '{}' has an empty map", builder.id)));
+						}
+						builder.in_map = false;
+						builder.finished = true;
+					} else {
+						match parse_row(row, &builder.legend) {
+							Ok(cells) => {
+								if !builder.rows.is_empty() && builder.rows[0].len() != cells.len() {
+									return Err(error(format!("map rows of vault '{}' must all be {} wide", builder.id, builder.rows[0].len())));
+								}
+								builder.rows.push(cells);
+							},
+							Err(e) => return Err(error(e))
+						}
+					}
+					continue;
+				}
+			}
+
+			let line = raw_line.trim();
+			if line.is_empty() || line.starts_with("#") {
+				continue;
+			}
+
+			if line.starts_with("[") {
+				if !line.ends_with("]") || line.len() < 3 {
+					return Err(error(format!("bad vault header '{}'", line)));
+				}
+				let id = line.slice(1, line.len() - 1).trim();
+				if builders.iter().any(|builder| builder.id.as_slice() == id) {
+					return Err(error(format!("vault '{}' is defined twice", id)));
+				}
+				if let Some(builder) = builders.last() {
+					if !builder.finished {
+						return Err(error(format!("vault '{}' has no map", builder.id)));
+					}
+				}
+				builders.push(VaultBuilder {
+					id: id.to_string(),
+					weight: 0,
+					min_depth: 1,
+					legend: Vec::new(),
+					rows: Vec::new(),
+					in_map: false,
+					finished: false
+				});
+				continue;
+			}
+
+			let builder = match builders.last_mut() {
+				Some(builder) => builder,
+				None => return Err(error("line outside of a [vault] section".to_string()))
+			};
+			if builder.finished {
+				return Err(error(format!("vault '{}' continues after its map", builder.id)));
+			}
+
+			if line == "map" {
+				builder.in_map = true;
+				continue;
+			}
+
+			let (key, value) = match line.find('=') {
+				Some(split) => (line.slice_to(split).trim(), line.slice_from(split + 1).trim()),
+				None => return Err(error(format!("expected 'key = value', found '{}'", line)))
+			};
+
+			match set_field(builder, key, value) {
+				Ok(()) => {},
+				Err(e) => return Err(error(e))
+			}
+		}
+
+		let mut vaults = Vec::with_capacity(builders.len());
+		for builder in builders.into_iter() {
+			if !builder.finished {
+				return Err(format!("{}: vault '{}' has no complete map", origin, builder.id));
+			}
+			vaults.push(Vault {id: builder.id, weight: builder.weight, min_depth: builder.min_depth, rows: builder.rows});
+		}
+		Ok(VaultRegistry {vaults: vaults})
+	}
+
+	// every actor a vault places has to exist in templates
+	pub fn check_templates(&self, templates: &TemplateRegistry) -> Result<(), String> {
+		for vault in self.vaults.iter() {
+			for row in vault.rows.iter() {
+				for cell in row.iter() {
+					if let VaultCell::Actor(ref id) = *cell {
+						if templates.get(id.as_slice()).is_none() {
+							return Err(format!("vault '{}' places unknown template '{}'", vault.id, id));
+						}
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+
+	// weighted random pick among the vaults allowed at depth
+	pub fn pick(&self, depth: uint, rng: &mut GameRng) -> Option<&Vault> {
+		let total = self.vaults.iter()
+			.filter(|vault| vault.min_depth <= depth)
+			.fold(0u, |sum, vault| sum + vault.weight);
+		if total == 0 {
+			return None;
+		}
+
+		let mut roll = rng.gen_range(0u, total);
+		for vault in self.vaults.iter().filter(|vault| vault.min_depth <= depth) {
+			if roll < vault.weight {
+				return Some(vault);
+			}
+			roll -= vault.weight;
+		}
+		None
+	}
+}
+
+fn set_field(builder: &mut VaultBuilder, key: &str, value: &str) -> Result<(), String> {
+	match key {
+		"weight" => {
+			builder.weight = match value.parse::<uint>() {
+				Some(weight) => weight,
+				None => return Err(format!("weight expects a number, found '{}'", value))
+			};
+		},
+		"min_depth" => {
+			builder.min_depth = match value.parse::<uint>() {
+				Some(depth) if depth > 0 => depth,
+				_ => return Err(format!("min_depth expects a number from 1, found '{}'", value))
+			};
+		},
+		_ => {
+			// anything else is a legend entry
+			if key.char_len() != 1 {
+				return Err(format!("unknown key '{}'", key));
+			}
+			let symbol = key.char_at(0);
+			if symbol == '#' || symbol == '.' || symbol == ',' {
+				return Err(format!("'{}' is a map symbol and can't place an actor", symbol));
+			}
+			if value.is_empty() {
+				return Err(format!("'{}' needs a template id", symbol));
+			}
+			builder.legend.push((symbol, value.to_string()));
+		}
+	}
+	Ok(())
+}
+
+fn parse_row(row: &str, legend: &Vec<(char, String)>) -> Result<Vec<VaultCell>, String> {
+	let mut cells = Vec::with_capacity(row.len());
+	for symbol in row.chars() {
+		let cell = match symbol {
+			'#' => VaultCell::Wall,
+			'.' => VaultCell::Floor,
+			',' => VaultCell::Untouched,
+			_ => match legend.iter().find(|&&(legend_symbol, _)| legend_symbol == symbol) {
+				Some(&(_, ref template)) => VaultCell::Actor(template.clone()),
+				None => return Err(format!("'{}' is not in the legend", symbol))
+			}
+		};
+		cells.push(cell);
+	}
+	Ok(cells)
+}
@@ -1,70 +1,206 @@
 extern crate core;
 
-use util::Point;
+use util::{Point, GameRng, Color};
 use actor::Actor;
+use arena::{ActorArena, ActorId};
+use input::{InputSource, ScriptedInput, KeyCode};
+use scheduler;
+use action;
+use action::BoxedAction;
+use system;
+use fov;
+use pathfinding;
+use pathfinding::{PathGrid, PathOptions};
+use dijkstra::NavMaps;
+use perception::{Noise, NoiseListener};
+use tactics::Tactics;
+use dungeon;
+use dungeon::Level;
+use event;
+use event::{EventBus, GameEvent, ActorInfo, MessageListener, KillListener};
+use template::TemplateRegistry;
+use vault::VaultRegistry;
+use mapfile::MapData;
+use scheduler::Scheduled;
 
-use std::cell::{RefCell};
-use std::rc::{Rc};
 use std::collections::RingBuf;
+use std::cell::{Cell as SharedCell, RefCell};
+use std::rc::Rc;
+
+// an actor's scheduling state, copied out of the arena for the scheduler and
+// written back afterwards. Only actors with an ai component take turns.
+struct TurnEntry {
+	id: ActorId,
+	speed: int,
+	energy: int,
+	can_act: bool
+}
 
-use tcod::AStarPath;
+impl Scheduled for TurnEntry {
+	fn speed(&self) -> int {
+		self.speed
+	}
 
-pub type ActorRef = Rc<RefCell<Actor>>;
+	fn energy(&self) -> int {
+		self.energy
+	}
+
+	fn set_energy(&mut self, energy: int) {
+		self.energy = energy;
+	}
 
-#[deriving(PartialEq)]
+	fn can_act(&self) -> bool {
+		self.can_act
+	}
+}
+
+#[deriving(PartialEq, Clone)]
 pub enum CellType {
     Wall,
-    Floor
+    Floor,
+    StairsDown,
+    StairsUp,
+    DoorClosed,
+    DoorOpen,
+    Water,
+    Chasm,
+    Rubble,
+    Lava
+}
+
+// hit points lava takes from whoever steps into it
+pub static LAVA_DAMAGE: int = 2;
+
+impl CellType {
+	pub fn glyph(&self) -> char {
+		match *self {
+			CellType::Wall => '#',
+			CellType::Floor => '.',
+			CellType::StairsDown => '>',
+			CellType::StairsUp => '<',
+			CellType::DoorClosed => '+',
+			CellType::DoorOpen => '/',
+			CellType::Water => '~',
+			CellType::Chasm => ':',
+			CellType::Rubble => '%',
+			CellType::Lava => '='
+		}
+	}
+
+	pub fn from_glyph(glyph: char) -> Option<CellType> {
+		match glyph {
+			'#' => Some(CellType::Wall),
+			'.' => Some(CellType::Floor),
+			'>' => Some(CellType::StairsDown),
+			'<' => Some(CellType::StairsUp),
+			'+' => Some(CellType::DoorClosed),
+			'/' => Some(CellType::DoorOpen),
+			'~' => Some(CellType::Water),
+			':' => Some(CellType::Chasm),
+			'%' => Some(CellType::Rubble),
+			'=' => Some(CellType::Lava),
+			_ => None
+		}
+	}
+
+	pub fn color(&self) -> Color {
+		match *self {
+			CellType::DoorClosed | CellType::DoorOpen => Color::new(160, 110, 50),
+			CellType::Water => Color::new(40, 90, 220),
+			CellType::Chasm => Color::new(70, 40, 90),
+			CellType::Rubble => Color::new(150, 150, 130),
+			CellType::Lava => Color::new(255, 90, 0),
+			_ => Color::white()
+		}
+	}
+
+	// whether actors can stand on it
+	pub fn is_walkable(&self) -> bool {
+		match *self {
+			CellType::Wall | CellType::DoorClosed | CellType::Chasm | CellType::Rubble => false,
+			_ => true
+		}
+	}
+
+	// whether it's in the way of a path, closed doors can be opened on the way
+	pub fn is_passable(&self) -> bool {
+		self.is_walkable() || *self == CellType::DoorClosed
+	}
+
+	pub fn is_transparent(&self) -> bool {
+		match *self {
+			CellType::Wall | CellType::DoorClosed => false,
+			_ => true
+		}
+	}
+
+	// projectiles fly over water, lava and chasms but not through these
+	pub fn stops_projectiles(&self) -> bool {
+		match *self {
+			CellType::Wall | CellType::DoorClosed | CellType::Rubble => true,
+			_ => false
+		}
+	}
+
+	// energy to step onto it, in percent of a normal move
+	pub fn move_cost(&self) -> int {
+		match *self {
+			CellType::Water => 200,
+			_ => 100
+		}
+	}
+
+	// what a path over it costs, in percent of a normal move. Lava is quick to
+	// cross but hurts, paths go a long way round it.
+	pub fn path_cost(&self) -> int {
+		match *self {
+			CellType::Lava => 1000,
+			_ => self.move_cost()
+		}
+	}
 }
 
 struct Cell {
 	pub cell_type: CellType,
-	pub actor: Option<ActorRef>
+	pub actor: Option<ActorId>,
+	// in the player's field of view right now
+	pub visible: bool,
+	// seen at some point, drawn from memory when not visible
+	pub explored: bool
 }
 
 impl Cell {
 	fn new(cell_type: CellType) -> Cell {
-		Cell {cell_type: cell_type, actor: None}
+		Cell {cell_type: cell_type, actor: None, visible: false, explored: false}
 	}
 
 	pub fn get_glyph(&self) -> char {
-		match self.cell_type {
-			CellType::Wall => '#',
-			CellType::Floor => '.'
-		}
-	}
-
-	pub fn is_walkable(&self) -> bool {
-		match self.cell_type {
-			CellType::Floor => { 
-				match self.actor {
-					Some(ref actor) => { 
-						return !actor.borrow().is_solid; 
-					}
-					None => { return true; }
-				}
-			},
-			_ => { return false; }
-		}
+		self.cell_type.glyph()
 	}
 
 	pub fn clear(&mut self) {
 		self.actor = None;
+		self.visible = false;
+		self.explored = false;
 	}
 
 }
 
 pub struct PlayerState {
-	pub ammo : uint,
-	pub kills : uint,
+	// counted by a KillListener
+	pub kills : Rc<SharedCell<uint>>,
 	pub is_aiming: bool,
+	pub quit_requested: bool,
+	// depth the player took the stairs to, the level changes at the end of the tick
+	pub travel_to: Option<uint>,
 }
 
 impl PlayerState {
 	pub fn reset(&mut self) {
-		self.ammo = 0;
-		self.kills = 0;
+		self.kills.set(0);
 		self.is_aiming = false;
+		self.quit_requested = false;
+		self.travel_to = None;
 	}
 
 	pub fn toggle_aiming(&mut self) {
@@ -78,15 +214,47 @@ pub struct World {
 	pub width: uint,
 	pub height: uint,
 	pub grid: Vec<Vec<Cell>>,
-	pub actors: Vec<ActorRef>,
-	pub player: Box<ActorRef>,
+	pub actors: ActorArena,
+	pub player: ActorId,
 	pub player_state : Box<PlayerState>,
-	to_act: RingBuf<ActorRef>,
-	pub messages : RingBuf<String>
+	// kept by a MessageListener
+	pub messages : Rc<RefCell<RingBuf<String>>>,
+	pub input: Box<InputSource + 'static>,
+	// every key the player brain consumed since the run started, for replays
+	pub input_log: Vec<KeyCode>,
+	pub awaiting_input: bool,
+	pub seed: u32,
+	pub rng: GameRng,
+	pub events: EventBus,
+	// what generators and spawners build actors from
+	pub templates: TemplateRegistry,
+	// set pieces the generator stamps into levels
+	pub vaults: VaultRegistry,
+	// 1 is the top level
+	pub depth: uint,
+	// levels the player has left, see dungeon
+	pub levels: Vec<Level>,
+	// shared monster navigation, None when it has to be rebuilt
+	nav: Option<NavMaps>,
+	// what monsters can hear since the player last acted, collected by a
+	// NoiseListener
+	pub noises: Rc<RefCell<Vec<Noise>>>,
+	// which monster surrounds the player from where
+	pub tactics: Tactics,
+	// the player moved or the terrain changed since the fov was computed
+	fov_dirty: bool,
+	// the hand made map the run started on, None for a generated one
+	pub start_map: Option<MapData>
 }
 
 impl World {
+	// a world nobody plays, e.g. for generating maps
 	pub fn new(width: uint, height: uint) -> World {
+		World::with_input(width, height, box ScriptedInput::new(Vec::new()))
+	}
+
+	// a world whose player is driven by input, the console or a headless source
+	pub fn with_input(width: uint, height: uint, input: Box<InputSource + 'static>) -> World {
 
 		let mut cols:Vec<Vec<Cell>> = Vec::with_capacity(width);
 
@@ -98,124 +266,333 @@ impl World {
 			cols.push(rows);
 		}
 
-		let player = Actor::player();
-		let player_state = PlayerState {ammo: 0, kills: 0, is_aiming: false};
-		let player_ref = Rc::new(RefCell::new(player));
-		let mut actors = Vec::new();
-		actors.push(player_ref.clone());
+		let mut actors = ActorArena::new();
+		let player = actors.insert(Actor::player());
+
+		// the log, kill count and noises are kept up to date by listening
+		let mut events = EventBus::new();
+		let message_listener = MessageListener::new();
+		let messages = message_listener.messages.clone();
+		events.subscribe(box message_listener);
+		let kill_listener = KillListener::new();
+		let kills = kill_listener.kills.clone();
+		events.subscribe(box kill_listener);
+		let noise_listener = NoiseListener::new();
+		let noises = noise_listener.noises.clone();
+		events.subscribe(box noise_listener);
+
+		let player_state = PlayerState {kills: kills, is_aiming: false, quit_requested: false, travel_to: None};
 
 		World {	
 				width: width, 
 				height: height, 
 				grid: cols, 
 				actors: actors, 
-				player: box player_ref, 
+				player: player, 
 				player_state: box player_state, 
-				to_act: RingBuf::new(), 
-				messages: RingBuf::new()
+				messages: messages,
+				input: input,
+				input_log: Vec::new(),
+				awaiting_input: false,
+				seed: 0,
+				rng: GameRng::new(0),
+				events: events,
+				templates: TemplateRegistry::builtin(),
+				vaults: VaultRegistry::builtin(),
+				depth: 1,
+				levels: Vec::new(),
+				nav: None,
+				noises: noises,
+				tactics: Tactics::new(),
+				fov_dirty: true,
+				start_map: None
 			}
 	} 
 
+	// empties the world except for a fresh player, who has no position yet
 	pub fn cleanup(&mut self) {
 
 		self.actors.clear();
+		self.player = self.actors.insert(Actor::player());
 		for y in range (0, self.height) {
 			for x in range(0, self.width) {
 				self.grid[y][x].clear();
 			}
 		}
-		self.player.borrow_mut().health = 10;
+		self.messages.borrow_mut().clear();
+		self.input_log.clear();
+		self.depth = 1;
+		self.levels.clear();
+		self.nav = None;
+		self.noises.borrow_mut().clear();
+		self.tactics.clear();
+		self.fov_dirty = true;
+		self.start_map = None;
+		self.awaiting_input = false;
 		self.player_state.reset();
 	}
 
+	// every random decision of the next run derives from this seed
+	pub fn reseed(&mut self, seed: u32) {
+		self.seed = seed;
+		self.rng = GameRng::new(seed);
+	}
+
+	// next key for the player, recorded so the run can be replayed
+	pub fn next_key(&mut self) -> Option<KeyCode> {
+		let key = self.input.next_key();
+		match key {
+			Some(ref key_code) => {
+				self.input_log.push(key_code.clone());
+				self.awaiting_input = false;
+			},
+			None => {
+				self.awaiting_input = true;
+			}
+		}
+		key
+	}
+
 	pub fn is_game_over(&self) -> bool {
-		return !self.player.borrow().is_alive();
+		return !self.actors.get(&self.player).map_or(false, |player| player.is_alive());
 	}
 
-	pub fn increase_kills(&mut self) {
-		self.player_state.kills += 1;
+	pub fn player(&self) -> &Actor {
+		self.actors.get(&self.player).expect("player is always in the arena")
+	}
+
+	pub fn player_ammo(&self) -> uint {
+		self.player().inventory.as_ref().map_or(0, |inventory| inventory.ammo)
 	}
 
 	pub fn has_ammo(&self) -> bool {
-		return self.player_state.ammo > 0;
+		return self.player_ammo() > 0;
 	}
 
-	pub fn decrease_ammo(&mut self) {
-		self.player_state.ammo -= 1;
+	pub fn tick(&mut self) {
+
+		if let Some(id) = self.next_to_act() {
+			let can_act = match self.actors.get(&id).and_then(|actor| actor.ai.as_ref()) {
+				Some(ai) => ai.brain.think(&mut self.rng),
+				None => false
+			};
+
+			if can_act {
+				let action_option = self.act(&id);
+		 		match action_option {
+		 			Some(chosen_action) => {
+		 				if id == self.player {
+		 					// everyone had a turn to hear what the player did last
+		 					self.noises.borrow_mut().clear();
+		 				}
+		 				let result = action::perform(&chosen_action, &id, self);
+		 				for event in result.events.iter() {
+		 					self.publish(event);
+		 				}
+		 			},
+		 			None => {
+		 				// no action taken (player). still the most energetic, so it's picked again next tick
+		 			}
+		 		}
+			} else if let Some(actor) = self.actors.get_mut(&id) {
+				// decided to sit this turn out
+				actor.spend_energy(scheduler::ACTION_ENERGY);
+			}
+		}
+		
+		system::sweep(self);
+
+		if let Some(depth) = self.player_state.travel_to.take() {
+			dungeon::travel(self, depth);
+		}
+		if self.fov_dirty {
+			self.update_fov();
+		}
 	}
 
-	pub fn increase_ammo(&mut self, amount: uint) {
-		self.player_state.ammo += amount;
+	// recomputes what the player sees from where it stands
+	pub fn update_fov(&mut self) {
+		self.fov_dirty = false;
+		for y in range(0, self.height) {
+			for x in range(0, self.width) {
+				self.grid[y][x].visible = false;
+			}
+		}
+
+		let position = match self.actors.get(&self.player).and_then(|player| player.position.clone()) {
+			Some(position) => position,
+			None => return
+		};
+		let visible = fov::compute(self, &position, fov::FOV_RADIUS);
+		for p in visible.iter() {
+			let cell = &mut self.grid[p.y][p.x];
+			cell.visible = true;
+			cell.explored = true;
+		}
 	}
 
-	pub fn tick(&mut self) {
+	pub fn is_visible(&self, p: &Point) -> bool {
+		self.is_valid(p) && self.get_cell(p.x, p.y).visible
+	}
+
+	pub fn blocks_sight(&self, p: &Point) -> bool {
+		!self.get_cell(p.x, p.y).cell_type.is_transparent()
+	}
+
+	pub fn cell_type_at(&self, p: &Point) -> CellType {
+		self.get_cell(p.x, p.y).cell_type.clone()
+	}
+
+	pub fn is_closed_door(&self, p: &Point) -> bool {
+		self.is_valid(p) && self.get_cell(p.x, p.y).cell_type == CellType::DoorClosed
+	}
+
+	pub fn set_cell_type(&mut self, p: &Point, cell_type: CellType) {
+		self.grid[p.y][p.x].cell_type = cell_type;
+		self.nav = None;
+		self.fov_dirty = true;
+	}
 
-		if self.to_act.is_empty() {
-			for actor_ref in self.actors.iter_mut() {
-				let actor = actor_ref.borrow();
-			 	let can_act = actor.brain.think();
-			 	if can_act {
-			 		self.to_act.push_back(actor_ref.clone());
-			 	}
+	// Distance maps toward and away from the player and toward ammo, built at
+	// most once per player position and shared by every monster.
+	pub fn nav_maps(&mut self) -> &NavMaps {
+		let player_position = self.get_player_position();
+		let stale = self.nav.as_ref().map_or(true, |nav| nav.player_position != player_position);
+		if stale {
+			let nav = NavMaps::build(&*self, &player_position, self.ammo_positions().as_slice());
+			self.nav = Some(nav);
+		}
+		self.nav.as_ref().unwrap()
+	}
+
+	// the level changed under the maps, sounds and claims, e.g. after taking
+	// the stairs
+	pub fn invalidate_nav(&mut self) {
+		self.nav = None;
+		self.noises.borrow_mut().clear();
+		self.tactics.clear();
+	}
+
+	fn ammo_positions(&self) -> Vec<Point> {
+		let mut positions = Vec::new();
+		for id in self.actors.ids().iter() {
+			let actor = self.actors.get(id).unwrap();
+			if let (Some(_), Some(ref position)) = (actor.pickup.as_ref(), actor.position.as_ref()) {
+				positions.push(position.clone());
 			}
 		}
+		positions
+	}
 
-		if let Some(actor_ref) = self.to_act.pop_front() {
-			let mut actor_alive;
-			let mut action_option;
-			{
-				let mut actor = actor_ref.borrow_mut();
-				actor_alive = actor.is_alive();
-				
-				if actor_alive {
-					action_option = actor.act(self);	
-				} else {
-					action_option = None
+	fn next_to_act(&mut self) -> Option<ActorId> {
+		let mut entries = Vec::with_capacity(self.actors.len());
+		for id in self.actors.ids().into_iter() {
+			let actor = self.actors.get(&id).unwrap();
+			if let Some(ref ai) = actor.ai {
+				entries.push(TurnEntry {id: id.clone(), speed: ai.speed, energy: ai.energy, can_act: actor.is_alive() && ai.speed > 0});
+			}
+		}
+
+		let next = scheduler::next_to_act(entries.as_mut_slice());
+
+		for entry in entries.iter() {
+			if let Some(ref mut ai) = self.actors.get_mut(&entry.id).unwrap().ai {
+				ai.energy = entry.energy;
+			}
+		}
+		next.map(|index| entries[index].id.clone())
+	}
+
+	// The brain is taken out of its actor while it decides, so it can look at and
+	// change the world, this actor included, without aliasing itself.
+	fn act(&mut self, id: &ActorId) -> Option<BoxedAction> {
+		let (mut ai, position) = match self.actors.get_mut(id) {
+			Some(actor) => match (actor.ai.take(), actor.position.clone()) {
+				(Some(ai), Some(position)) => (ai, position),
+				(ai, _) => {
+					actor.ai = ai;
+					return None;
 				}
+			},
+			None => return None
+		};
+
+		let chosen_action = ai.brain.act(&position, self);
+
+		if let Some(actor) = self.actors.get_mut(id) {
+			actor.ai = Some(ai);
+		}
+		chosen_action
+	}
+
+	pub fn publish(&mut self, event: &GameEvent) {
+		self.events.publish(event);
+	}
+
+	pub fn set_actor_position(&mut self, id: &ActorId, position: &Point) {
+		if *id == self.player {
+			self.fov_dirty = true;
+		}
+		if let Some(actor) = self.actors.get_mut(id) {
+			// actors that are new to the world have no cell to leave
+			if let Some(ref p) = actor.position {
+				self.grid[p.y][p.x].actor = None;
 			}
-	 		match action_option {
-	 			Some(action) => {
-	 				action.execute(&actor_ref, self);
-	 			},
-	 			None => {
-	 				// no action taken (player). check again next tick
-	 				if actor_alive {
-	 					self.to_act.push_front(actor_ref.clone());	
-	 				}
-	 			}
-	 		}
+			self.grid[position.y][position.x].actor = Some(id.clone());
+			actor.set_position(position.clone());
 		}
-		
-		// clean up dead actors
-		self.actors.retain(|ref actor_ref| {
-			actor_ref.borrow().is_alive()
-		});
-		
 	}
 
-	pub fn set_actor_position(&mut self, actor_ref: &ActorRef, position: &Point) {
-		let mut actor = actor_ref.borrow_mut();
-		{
-			let p = actor.get_position();
-			let current_position = Point::new(p.x, p.y);
-			
-			self.grid[current_position.y][current_position.x].actor = None;
-			self.grid[position.y][position.x].actor = Some(actor_ref.clone());
+	pub fn add_actor(&mut self, actor: Actor, position: Point) -> ActorId {
+		if actor.pickup.is_some() {
+			self.nav = None;
 		}
-		
-		// set new location
-		let new_position = Point::new(position.x, position.y);
-		actor.deref_mut().set_position(new_position);
+		let id = self.actors.insert(actor);
+		self.set_actor_position(&id, &position);
+		id
 	}
 
-	pub fn add_actor(&mut self, actor: Actor, position: Point) {
-		let actor_ref = Rc::new(RefCell::new(actor));
-		self.set_actor_position(&actor_ref, &position);
-		self.actors.push(actor_ref.clone());
+	// puts back an actor that already knows its position, e.g. from a save
+	pub fn restore_actor(&mut self, actor: Actor) -> ActorId {
+		let position = actor.position.clone();
+		let is_player = actor.is_player;
+		let id = self.actors.insert(actor);
+		if let Some(position) = position {
+			self.grid[position.y][position.x].actor = Some(id.clone());
+		}
+		if is_player {
+			self.player = id.clone();
+		}
+		id
 	}
 
-	pub fn remove_actor(&mut self, position: &Point) {
-		self.grid[position.y][position.x].actor = None;
+	// Takes an actor out of the grid and the arena. The player stays in the arena
+	// after dying so the panels and game over screen can still show it.
+	pub fn remove_actor(&mut self, id: &ActorId) {
+		if self.actors.get(id).map_or(false, |actor| actor.pickup.is_some()) {
+			self.nav = None;
+		}
+		let position = self.actors.get(id).and_then(|actor| actor.position.clone());
+		if let Some(position) = position {
+			if self.grid[position.y][position.x].actor.as_ref() == Some(id) {
+				self.grid[position.y][position.x].actor = None;
+			}
+		}
+		if *id != self.player {
+			self.actors.remove(id);
+		}
+	}
+
+	// None once the actor has been removed
+	pub fn actor_info(&self, id: &ActorId) -> Option<ActorInfo> {
+		self.actors.get(id).map(|actor| ActorInfo::of(id, actor))
+	}
+
+	pub fn actor_at(&self, p: &Point) -> Option<ActorId> {
+		if !self.is_valid(p) {
+			return None;
+		}
+		self.get_cell(p.x, p.y).actor.clone()
 	}
 
 	pub fn is_valid(&self, p: &Point) -> bool {
@@ -223,14 +600,26 @@ impl World {
 	}
 
 	pub fn is_walkable(&self, p: &Point) -> bool {
-		return self.is_valid(p) && self.get_cell(p.x, p.y).is_walkable();
+		if !self.is_valid(p) {
+			return false;
+		}
+		let cell = self.get_cell(p.x, p.y);
+		if !cell.cell_type.is_walkable() {
+			return false;
+		}
+		match cell.actor {
+			Some(ref id) => {
+				return !self.actors.get(id).map_or(false, |actor| actor.is_solid());
+			}
+			None => { return true; }
+		}
 	}
 
 	pub fn is_bumpable(&self, p: &Point, only_bump_player: bool) -> bool {
 		let cell = self.get_cell(p.x, p.y);
-    	if let Some(ref actor_ref) = cell.actor {
+    	if let Some(ref id) = cell.actor {
     		if only_bump_player {
-    			return actor_ref.borrow().is_player;
+    			return *id == self.player;
     		} else {
     			return true;	
     		}
@@ -244,46 +633,39 @@ impl World {
 	}
 
 	pub fn get_player_position(&self) -> Point {
-		let player = self.player.borrow();
-		Point::new(player.position.x, player.position.y)
+		self.player().get_position().clone()
 	}
 
+	// a line for the log that isn't about an event
 	pub fn add_message(&mut self, message: &str) {
-		self.messages.push_back(String::from_str(message));
-		if self.messages.len() > 3 {
-			self.messages.pop_front();
-		}
+		event::log(&self.messages, String::from_str(message));
 	}
 
-	#[allow(unused_variables)]
-	pub fn find_path(&mut self, from_position: &Point, to_position: &Point) -> Option<Vec<Point>>{
-		let diagonal_cost = 0.0;
-	    let w = self.width as int;
-	    let h = self.height as int;
-		
-		let can_move = move |&mut: from: (int, int), to: (int, int)| -> f32 {
-	        let (tx, ty) = to;
-
-	        if self.get_cell(tx as uint,ty as uint).cell_type == CellType::Wall {
-	        	0.0
-	        } else {
-	        	1.0
-	        }
-	    };
-	    
-	   	let mut pathfinder = AStarPath::new_from_callback(w, h, can_move, diagonal_cost);
-	    let found_path = pathfinder.find((from_position.x as int, from_position.y as int), (to_position.x as int, to_position.y as int));
-		if found_path {
-			let mut path_vector = Vec::new();
-			for pos in pathfinder.walk() {
-				let (x, y) = pos;
-		        path_vector.push(Point::new(x as uint, y as uint));
-		    }
-			Some(path_vector)
-		} else {
-			None
-		}
-	    
-	}
-
-}
\ No newline at end of file
+	pub fn find_path(&self, from_position: &Point, to_position: &Point, options: &PathOptions) -> Option<Vec<Point>> {
+		pathfinding::find_path(self, from_position, to_position, options)
+	}
+
+}
+
+impl PathGrid for World {
+	fn size(&self) -> (uint, uint) {
+		(self.width, self.height)
+	}
+
+	// closed doors count as plain floor, opening them is the walker's problem
+	fn step_cost(&self, p: &Point) -> Option<uint> {
+		let cell_type = &self.get_cell(p.x, p.y).cell_type;
+		if cell_type.is_passable() { Some(cell_type.path_cost() as uint) } else { None }
+	}
+
+	fn cell_type(&self, p: &Point) -> CellType {
+		self.cell_type_at(p)
+	}
+
+	fn is_occupied(&self, p: &Point) -> bool {
+		match self.get_cell(p.x, p.y).actor {
+			Some(ref id) => self.actors.get(id).map_or(false, |actor| actor.is_solid()),
+			None => false
+		}
+	}
+}